@@ -0,0 +1,33 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+extern crate mnt;
+
+use mnt::genfstab_from_proc;
+use std::io::{self, Write};
+
+
+fn main() {
+    match genfstab_from_proc() {
+        Ok(entries) => {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            for entry in entries.iter() {
+                // Keep the non UTF-8 paths as is
+                let _ = out.write_all(&entry.to_bytes()).and_then(|_| out.write_all(b"\n"));
+            }
+        },
+        Err(e) => println!("Error: {}", e),
+    }
+}
@@ -20,7 +20,7 @@ use std::path::{Path, PathBuf};
 
 
 fn list_mount(target: &Path) {
-    match get_mount(&target) {
+    match get_mount(target) {
         Ok(list) => {
             match list {
                 Some(mount) => println!("Mount point: {:?}", mount),
@@ -32,7 +32,7 @@ fn list_mount(target: &Path) {
 }
 
 fn main() {
-    let target = match args().skip(1).next() {
+    let target = match args().nth(1) {
         Some(target) => PathBuf::from(target),
         None => PathBuf::from("/"),
     };
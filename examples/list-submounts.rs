@@ -20,7 +20,7 @@ use std::path::{Path, PathBuf};
 
 
 fn list_submounts(root: &Path) {
-    match get_submounts(&root) {
+    match get_submounts(root) {
         Ok(list) => {
             for mount in list.remove_overlaps(&Vec::<&Path>::new()).iter() {
                 println!("* {:?}", mount);
@@ -31,7 +31,7 @@ fn list_submounts(root: &Path) {
 }
 
 fn main() {
-    let root = match args().skip(1).next() {
+    let root = match args().nth(1) {
         Some(root) => PathBuf::from(root),
         None => PathBuf::from("/"),
     };
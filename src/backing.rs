@@ -0,0 +1,86 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use bind::join_suffix;
+use error::*;
+use mountinfo::{DeviceId, MountInfoEntry, MountTable};
+use parse::MountEntry;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Where a file really lives
+///
+/// Two paths alias the same data if they have the same `dev` and `fs_path`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackingLocation {
+    /// Mount containing the file
+    pub mount: MountEntry,
+    pub dev: DeviceId,
+    /// Path of the file relative to the root of its filesystem
+    pub fs_path: PathBuf,
+    /// Block device holding the filesystem, if any
+    pub block_device: Option<PathBuf>,
+}
+
+/// Get the device node of a block device from sysfs
+fn sysfs_block_device(dev: DeviceId) -> Option<PathBuf> {
+    let uevent = File::open(format!("/sys/dev/block/{}/uevent", dev)).ok()?;
+    BufReader::new(uevent).lines()
+        .map_while(Result::ok)
+        .find_map(|l| l.strip_prefix("DEVNAME=").map(|n| Path::new("/dev").join(n)))
+}
+
+impl BackingLocation {
+    /// Locate `path`, which must be in the mount `entry`
+    fn new(entry: &MountInfoEntry, path: &Path) -> Option<BackingLocation> {
+        let suffix = path.strip_prefix(&entry.mount_point).ok()?;
+        let block_device = sysfs_block_device(entry.dev).or_else(|| entry.source_device());
+        Some(BackingLocation {
+            mount: entry.to_mount_entry(),
+            dev: entry.dev,
+            fs_path: join_suffix(&entry.root, suffix),
+            block_device,
+        })
+    }
+}
+
+/// Get the backing mount, device and filesystem path of `target`
+///
+/// This extends `get_mount()` with the filesystem root from */proc/self/mountinfo*, which
+/// tells where bind mounts come from.  Symlinks are followed and `target` must exist.
+pub fn get_backing<T>(target: T) -> Result<Option<BackingLocation>, ParseError> where T: AsRef<Path> {
+    let locate = |e: io::Error| ParseError::access(target.as_ref(), e);
+    let table = MountTable::new_from_proc()?;
+    let path = fs::canonicalize(&target).map_err(locate)?;
+    Ok(table.resolve(&path).map_err(locate)?.and_then(|m| BackingLocation::new(m, &path)))
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use std::process;
+    use super::get_backing;
+
+    #[test]
+    fn test_get_backing() {
+        let backing = get_backing("/proc/self/fd").unwrap().unwrap();
+        assert_eq!(backing.mount.file, Path::new("/proc"));
+        assert_eq!(backing.fs_path, Path::new("/").join(process::id().to_string()).join("fd"));
+        assert_eq!(backing.block_device, None);
+        assert_eq!(get_backing("/proc").unwrap().unwrap().fs_path, Path::new("/"));
+        assert!(get_backing("/nonexistent").is_err());
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+extern crate mnt;
+
+use mnt::{Filesystems, LintOptions, MountIter, ParseError, Severity, diff_fstab, lint, lints_to_json};
+use std::env::args;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::process::exit;
+
+const USAGE: &str = "usage: mnt-lint [--json] [FSTAB]\n       mnt-lint diff [FSTAB]";
+
+/// Compare the fstab with the live mounts
+fn diff(fstab: PathBuf) {
+    let mut entries = match File::open(&fstab) {
+        Ok(f) => MountIter::new(BufReader::new(f)).with_source_file(&fstab).skip_comments().lenient(),
+        Err(e) => {
+            eprintln!("{}", ParseError::from(e).with_source_file(&fstab).render());
+            exit(2);
+        }
+    };
+    let diff = match diff_fstab(&mut entries) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("{}", e.render());
+            exit(2);
+        }
+    };
+    for skipped in entries.diagnostics() {
+        eprintln!("{}", skipped.render());
+    }
+    for d in diff.iter() {
+        println!("{}", d);
+    }
+    if !diff.is_empty() {
+        exit(1);
+    }
+}
+
+fn main() {
+    let mut json = false;
+    let mut subcommand = None;
+    let mut fstab = None;
+    for arg in args().skip(1) {
+        match arg.as_ref() {
+            "--json" => json = true,
+            "diff" if subcommand.is_none() && fstab.is_none() => subcommand = Some(diff),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if fstab.is_none() => fstab = Some(PathBuf::from(arg)),
+            _ => {
+                eprintln!("{}", USAGE);
+                exit(2);
+            }
+        }
+    }
+    let fstab = fstab.unwrap_or_else(|| PathBuf::from("/etc/fstab"));
+    if let Some(subcommand) = subcommand {
+        if json {
+            eprintln!("{}", USAGE);
+            exit(2);
+        }
+        return subcommand(fstab);
+    }
+
+    let options = LintOptions {
+        filesystems: Filesystems::new_from_proc().ok(),
+    };
+    let lints = match File::open(&fstab) {
+        Ok(f) => lint(BufReader::new(f), &options),
+        Err(e) => Err(ParseError::from(e).with_source_file(&fstab)),
+    };
+    let lints = match lints {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("{}", e.render());
+            exit(2);
+        }
+    };
+
+    if json {
+        println!("{}", lints_to_json(&fstab, &lints));
+    } else {
+        for lint in lints.iter() {
+            println!("{}", lint.render(&fstab));
+        }
+    }
+    if lints.iter().any(|l| l.kind.severity() == Severity::Error) {
+        exit(1);
+    }
+}
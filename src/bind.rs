@@ -0,0 +1,116 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use mountinfo::{DeviceId, MountInfoEntry, MountTable};
+use parse::MntOps;
+use std::path::{Path, PathBuf};
+
+impl MountInfoEntry {
+    /// Get the directory of the filesystem mounted by default, e.g. the btrfs subvolume
+    pub(crate) fn fs_root(&self) -> &Path {
+        if self.vfstype == "btrfs" {
+            for ops in self.super_options.iter().chain(self.mntops.iter()) {
+                if let MntOps::Extra(ref extra) = *ops {
+                    if let Some(subvol) = extra.strip_prefix("subvol=") {
+                        return Path::new(subvol);
+                    }
+                }
+            }
+        }
+        Path::new("/")
+    }
+
+    /// Get the earlier mount of the same filesystem from which this one is bound
+    fn bind_parent<'a>(&self, table: &'a MountTable) -> Option<&'a MountInfoEntry> {
+        table.entries().iter()
+            .take_while(|m| m.mount_id != self.mount_id)
+            .filter(|m| m.dev == self.dev && self.root.starts_with(&m.root))
+            .min_by_key(|m| m.root.components().count())
+    }
+
+    /// Check if this mount is a bind mount
+    ///
+    /// A bind mount either exposes a subdirectory of its filesystem, or exposes a filesystem
+    /// already mounted earlier in `table`.  A filesystem mounted twice from its device can't be
+    /// told apart from a bind mount.
+    pub fn is_bind(&self, table: &MountTable) -> bool {
+        self.root != self.fs_root() || self.bind_parent(table).is_some()
+    }
+
+    /// Get the path from which this bind mount was made, according to the earlier mounts
+    pub fn bind_origin(&self, table: &MountTable) -> Option<PathBuf> {
+        let parent = self.bind_parent(table)?;
+        let suffix = self.root.strip_prefix(&parent.root).ok()?;
+        Some(join_suffix(&parent.mount_point, suffix))
+    }
+}
+
+/// Join a path relative to a filesystem root, without adding a trailing separator
+pub(crate) fn join_suffix(base: &Path, suffix: &Path) -> PathBuf {
+    if suffix.as_os_str().is_empty() {
+        base.to_path_buf()
+    } else {
+        base.join(suffix)
+    }
+}
+
+impl MountTable {
+    /// Get all the mounts exposing the directory `fs_path` of the device `dev`, along with its path
+    /// in each of them
+    ///
+    /// `fs_path` is relative to the root of the filesystem, as the mountinfo `root` field.
+    /// Mounts hidden by a later mount are included.
+    pub fn find_exposing<'a>(&'a self, dev: DeviceId, fs_path: &'a Path)
+            -> impl Iterator<Item = (&'a MountInfoEntry, PathBuf)> + 'a {
+        self.find_by_dev(dev).filter_map(move |m| {
+            let suffix = fs_path.strip_prefix(&m.root).ok()?;
+            Some((m, join_suffix(&m.mount_point, suffix)))
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use mountinfo::MountTable;
+    use std::path::{Path, PathBuf};
+
+    const MOUNTINFO: &[u8] = b"\
+        22 1 254:0 / / rw,relatime shared:1 - ext4 /dev/mapper/root rw\n\
+        30 22 254:1 / /srv rw,relatime - ext4 /dev/sdb1 rw\n\
+        31 22 0:40 /@home /home rw,relatime - btrfs /dev/sdc1 rw,subvol=/@home\n\
+        40 22 254:1 /data /jail/data ro,relatime - ext4 /dev/sdb1 rw\n\
+        41 22 254:1 / /jail/srv rw,relatime - ext4 /dev/sdb1 rw\n\
+        42 22 0:40 /@home/user /jail/user rw,relatime - btrfs /dev/sdc1 rw,subvol=/@home\n\
+    ";
+
+    #[test]
+    fn test_bind() {
+        let table = MountTable::from_fixture(MOUNTINFO);
+        let entry = |id| table.get_by_id(id).unwrap();
+        assert!(!entry(30).is_bind(&table));
+        assert!(!entry(31).is_bind(&table));
+        assert_eq!(entry(40).bind_origin(&table), Some(PathBuf::from("/srv/data")));
+        assert_eq!(entry(41).bind_origin(&table), Some(PathBuf::from("/srv")));
+        assert_eq!(entry(42).bind_origin(&table), Some(PathBuf::from("/home/user")));
+
+        let exposing: Vec<_> = table.find_exposing(entry(40).dev, Path::new("/data/foo"))
+            .map(|(m, p)| (m.mount_id, p)).collect();
+        assert_eq!(exposing, vec!(
+            (30, PathBuf::from("/srv/data/foo")),
+            (40, PathBuf::from("/jail/data/foo")),
+            (41, PathBuf::from("/jail/srv/data/foo")),
+        ));
+    }
+}
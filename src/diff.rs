@@ -0,0 +1,181 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use error::*;
+use genfstab::fstab_mounts;
+use mountinfo::{MountInfoEntry, MountTable};
+use parse::{MntOps, MountEntry};
+use source::{DeviceResolver, MountSource};
+use std::fmt;
+use std::fs;
+
+/// Difference between an fstab and the live mounts
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MountDiff {
+    /// fstab entry which isn't mounted, `noauto` ones excepted
+    NotMounted(MountEntry),
+    /// Visible live mount which isn't declared in the fstab, pseudo and API filesystems excepted
+    NotInFstab(MountEntry),
+    /// Mount whose effective options don't match the fstab ones
+    OptionsDiffer {
+        fstab: MountEntry,
+        live: MountEntry,
+        /// fstab options not in effect
+        missing: Vec<MntOps>,
+    },
+}
+
+impl fmt::Display for MountDiff {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MountDiff::NotMounted(ref m) =>
+                write!(out, "not mounted: {} on {}", m.spec.to_string_lossy(), m.file.display()),
+            MountDiff::NotInFstab(ref m) =>
+                write!(out, "not in fstab: {} on {}", m.spec.to_string_lossy(), m.file.display()),
+            MountDiff::OptionsDiffer { ref live, ref missing, .. } => {
+                let missing: Vec<String> = missing.iter().map(|o| o.to_string()).collect();
+                write!(out, "options differ: {} is not {}", live.file.display(), missing.join(","))
+            }
+        }
+    }
+}
+
+/// Get the fstab VFS options which are not in effect in the live options
+///
+/// Filesystem-specific options are ignored because the kernel may show them differently.
+fn missing_options(fstab: &MountEntry, live: &MountEntry) -> Vec<MntOps> {
+    let live = live.mntops_set();
+    fstab.mntops_set().to_vec().into_iter().filter(|o| match *o {
+        MntOps::Write(_) | MntOps::Suid(_) | MntOps::Dev(_) | MntOps::Exec(_) => true,
+        MntOps::Atime(b) | MntOps::DirAtime(b) => !b,
+        MntOps::RelAtime(b) => b,
+        MntOps::Extra(_) => false,
+    }).filter(|o| !live.contains(o)).collect()
+}
+
+/// Check if a live mount comes from the source of an fstab entry
+fn same_source(resolver: &DeviceResolver, table: &MountTable, fstab: &MountEntry, live: &MountInfoEntry) -> bool {
+    let mntops = fstab.mntops_set();
+    if mntops.contains(&MntOps::Extra("bind".into())) || mntops.contains(&MntOps::Extra("rbind".into())) {
+        return match live.bind_origin(table) {
+            Some(origin) => fs::canonicalize(&fstab.spec).map(|s| s == origin).unwrap_or(false),
+            None => false,
+        };
+    }
+    let source = fstab.mount_source();
+    let live_source = MountSource::parse(&live.source);
+    if let Ok(Some(dev)) = resolver.device_id(&source) {
+        let source_dev = live.source_device().and_then(|p| resolver.device_id(&MountSource::Device(p)).ok());
+        return dev == live.dev || source_dev == Some(Some(dev));
+    }
+    match (resolver.device_path(&source), resolver.device_path(&live_source)) {
+        (Ok(Some(a)), Ok(Some(b))) => a == b,
+        _ => fstab.spec == live.source,
+    }
+}
+
+/// Compare the `fstab` entries with the `live` mounts
+///
+/// Entries are matched on their resolved target and source.  Swap entries are ignored.
+pub fn diff_mounts<T>(fstab: T, live: &MountTable, resolver: &DeviceResolver) -> Result<Vec<MountDiff>, ParseError>
+        where T: IntoIterator<Item = Result<MountEntry, ParseError>> {
+    let mut ret = vec!();
+    let mut matched = vec!();
+    for entry in fstab {
+        let entry = entry?;
+        if entry.vfstype == "swap" {
+            continue;
+        }
+        let target = fs::canonicalize(&entry.file).unwrap_or_else(|_| entry.file.clone());
+        // The last mount on a target is the visible one
+        let found = live.entries().iter().rev()
+            .find(|m| m.mount_point == target && same_source(resolver, live, &entry, m));
+        match found {
+            Some(m) => {
+                matched.push(m.mount_id);
+                let live_entry = m.to_mount_entry();
+                let missing = missing_options(&entry, &live_entry);
+                if !missing.is_empty() {
+                    ret.push(MountDiff::OptionsDiffer {
+                        fstab: entry,
+                        live: live_entry,
+                        missing,
+                    });
+                }
+            }
+            None if !entry.mntops_set().is_noauto() => ret.push(MountDiff::NotMounted(entry)),
+            None => {}
+        }
+    }
+    ret.extend(fstab_mounts(live).filter(|m| !matched.contains(&m.mount_id))
+               .map(|m| MountDiff::NotInFstab(m.to_mount_entry())));
+    Ok(ret)
+}
+
+/// Compare the `fstab` entries with a snapshot of */proc/self/mountinfo*
+pub fn diff_fstab<T>(fstab: T) -> Result<Vec<MountDiff>, ParseError>
+        where T: IntoIterator<Item = Result<MountEntry, ParseError>> {
+    diff_mounts(fstab, &MountTable::new_from_proc()?, &DeviceResolver::new())
+}
+
+
+#[cfg(test)]
+mod test {
+    use mountinfo::MountTable;
+    use parse::{MntOps, MountIter};
+    use source::DeviceResolver;
+    use std::io::Cursor;
+    use super::{MountDiff, diff_mounts};
+
+    const MOUNTINFO: &[u8] = b"\
+        22 1 254:0 / / rw,relatime - ext4 /dev/nonexistent/root rw\n\
+        23 22 0:21 / /proc rw,nosuid,nodev,noexec,relatime - proc proc rw\n\
+        24 22 0:22 / /dev/shm rw,nosuid,nodev - tmpfs tmpfs rw\n\
+        25 22 0:23 / /sys/fs/cgroup rw,nosuid,nodev,noexec - cgroup2 cgroup2 rw\n\
+        30 22 254:1 / /nonexistent/srv ro,relatime - ext4 /dev/nonexistent/srv rw\n\
+        31 22 0:40 / /nonexistent/usb rw,relatime - vfat /dev/nonexistent/usb rw\n\
+        32 31 0:41 / /nonexistent/usb rw,relatime - vfat /dev/nonexistent/usb2 rw\n\
+        33 22 0:42 / /nonexistent/jail rw,relatime - tmpfs tmpfs rw\n\
+    ";
+
+    const FSTAB: &[u8] = b"\
+        /dev/nonexistent/root / ext4 defaults 0 1\n\
+        /dev/nonexistent/srv /nonexistent/srv ext4 rw,nosuid 0 2\n\
+        /dev/nonexistent/home /nonexistent/home ext4 defaults 0 2\n\
+        /dev/nonexistent/backup /nonexistent/backup ext4 noauto 0 2\n\
+        /dev/nonexistent/swap none swap sw 0 0\n\
+        proc /proc proc nosuid,nodev,noexec 0 0\n\
+        /nonexistent/srv /nonexistent/jail none bind 0 0\n\
+    ";
+
+    #[test]
+    fn test_diff_mounts() {
+        let table = MountTable::from_fixture(MOUNTINFO);
+        let resolver = DeviceResolver::new().with_disk_dir("/nonexistent");
+        let diff = diff_mounts(MountIter::new(Cursor::new(FSTAB)).skip_comments().lenient(), &table, &resolver).unwrap();
+        assert_eq!(diff.len(), 5);
+        match diff[0] {
+            MountDiff::OptionsDiffer { ref live, ref missing, .. } => {
+                assert_eq!(live.file.to_str(), Some("/nonexistent/srv"));
+                assert_eq!(missing, &vec!(MntOps::Write(true), MntOps::Suid(false)));
+            }
+            ref d => panic!("unexpected {:?}", d),
+        }
+        assert_eq!(diff[0].to_string(), "options differ: /nonexistent/srv is not rw,nosuid");
+        assert_eq!(diff[1].to_string(), "not mounted: /dev/nonexistent/home on /nonexistent/home");
+        assert_eq!(diff[2].to_string(), "not mounted: /nonexistent/srv on /nonexistent/jail");
+        assert_eq!(diff[3].to_string(), "not in fstab: /dev/nonexistent/usb2 on /nonexistent/usb");
+        assert_eq!(diff[4].to_string(), "not in fstab: tmpfs on /nonexistent/jail");
+    }
+}
@@ -13,70 +13,229 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 use std::borrow::Cow;
+use std::cmp;
 use std::error::Error;
 use std::fmt;
 use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// What went wrong while parsing a mount table
+#[derive(Debug)]
+pub enum ParseErrorKind {
+    Line(LineError),
+    Io(io::Error),
+    /// I/O error on a queried path, e.g. the target of a mount lookup, instead of a mount table
+    Access(PathBuf, io::Error),
+}
 
 #[derive(Debug)]
 pub struct ParseError {
-    desc: String,
-    // TODO: cause: Option<&'a (Error + 'a)>,
+    kind: ParseErrorKind,
+    line_number: Option<usize>,
+    source_file: Option<PathBuf>,
+    line: Option<Vec<u8>>,
 }
 
 impl ParseError {
-    pub fn new(detail: String) -> ParseError {
+    pub fn new(kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            kind,
+            line_number: None,
+            source_file: None,
+            line: None,
+        }
+    }
+
+    /// Create an error for a failed access to the queried `path`
+    pub fn access<T>(path: T, err: io::Error) -> ParseError where T: Into<PathBuf> {
+        ParseError::new(ParseErrorKind::Access(path.into(), err))
+    }
+
+    /// Create an error for the unparseable line number `line_number`, starting from 1
+    pub fn from_line<T>(err: LineError, line_number: usize, line: T) -> ParseError where T: Into<Vec<u8>> {
         ParseError {
-            desc: format!("Mount parsing: {}", detail),
+            line_number: Some(line_number),
+            line: Some(line.into()),
+            ..ParseError::new(ParseErrorKind::Line(err))
+        }
+    }
+
+    pub fn with_source_file<T>(mut self, path: T) -> ParseError where T: Into<PathBuf> {
+        self.source_file = Some(path.into());
+        self
+    }
+
+    pub fn kind(&self) -> &ParseErrorKind {
+        &self.kind
+    }
+
+    /// Number of the line where the error occurred, starting from 1
+    pub fn line_number(&self) -> Option<usize> {
+        self.line_number
+    }
+
+    /// Path of the parsed mount table, if known
+    pub fn source_file(&self) -> Option<&Path> {
+        self.source_file.as_deref()
+    }
+
+    /// Raw content of the unparseable line, without the trailing newline
+    pub fn line(&self) -> Option<&[u8]> {
+        self.line.as_deref()
+    }
+
+    /// Print the error with the unparseable line and a caret under the bad token, if any
+    pub fn render(&self) -> String {
+        match self.kind {
+            ParseErrorKind::Line(ref e) => match self.line() {
+                Some(line) => render_line_error(e, self.source_file(), self.line_number, line),
+                None => format!("error: {}: {}", self, e),
+            },
+            ParseErrorKind::Io(ref e) | ParseErrorKind::Access(_, ref e) => format!("error: {}: {}", self, e),
         }
     }
 }
 
 impl Error for ParseError {
-    fn description(&self) -> &str {
-        self.desc.as_ref()
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self.kind {
+            ParseErrorKind::Line(ref e) => Some(e),
+            ParseErrorKind::Io(ref e) | ParseErrorKind::Access(_, ref e) => Some(e),
+        }
     }
 }
 
 impl From<io::Error> for ParseError {
     fn from(err: io::Error) -> ParseError {
-        ParseError::new(format!("Failed to read the mounts file: {}", err))
+        ParseError::new(ParseErrorKind::Io(err))
+    }
+}
+
+impl From<LineError> for ParseError {
+    fn from(err: LineError) -> ParseError {
+        ParseError::new(ParseErrorKind::Line(err))
     }
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
-        write!(out, "{}", self.description())
+        let file: Cow<_> = match self.source_file {
+            Some(ref f) => f.to_string_lossy(),
+            None => "the mounts file".into(),
+        };
+        match self.kind {
+            ParseErrorKind::Line(_) => write!(out, "Mount parsing: Failed to parse {}", file)?,
+            ParseErrorKind::Io(_) => write!(out, "Mount parsing: Failed to read {}", file)?,
+            ParseErrorKind::Access(ref path, _) => return write!(out, "Mount lookup: Failed to access {}", path.display()),
+        }
+        match self.line_number {
+            Some(nb) => write!(out, " at line {}", nb),
+            None => Ok(()),
+        }
     }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub enum LineError {
+pub enum LineErrorKind {
     MissingSpec,
     MissingFile,
     InvalidFilePath(String),
     InvalidFile(String),
     MissingVfstype,
+    InvalidVfstype(String),
     MissingMntops,
+    InvalidMntops(String),
     MissingFreq,
     InvalidFreq(String),
     MissingPassno,
     InvalidPassno(String),
+    MissingField(&'static str),
+    InvalidField(&'static str, String),
 }
 
-impl fmt::Display for LineError {
+impl fmt::Display for LineErrorKind {
     fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
         let desc: Cow<_> = match *self {
-            LineError::MissingSpec => "Missing field #1 (spec)".into(),
-            LineError::MissingFile => "Missing field #2 (file)".into(),
-            LineError::InvalidFilePath(ref f) => format!("Bad field #2 (file) value (not absolute path): {}", f).into(),
-            LineError::InvalidFile(ref f) => format!("Bad field #2 (file) value: {}", f).into(),
-            LineError::MissingVfstype => "Missing field #3 (vfstype)".into(),
-            LineError::MissingMntops => "Missing field #4 (mntops)".into(),
-            LineError::MissingFreq => "Missing field #5 (freq)".into(),
-            LineError::InvalidFreq(ref f) => format!("Bad field #5 (dump) value: {}", f).into(),
-            LineError::MissingPassno => "Missing field #6 (passno)".into(),
-            LineError::InvalidPassno(ref f) => format!("Bad field #6 (passno) value: {}", f).into(),
+            LineErrorKind::MissingSpec => "Missing field #1 (spec)".into(),
+            LineErrorKind::MissingFile => "Missing field #2 (file)".into(),
+            LineErrorKind::InvalidFilePath(ref f) => format!("Bad field #2 (file) value (not absolute path): {}", f).into(),
+            LineErrorKind::InvalidFile(ref f) => format!("Bad field #2 (file) value: {}", f).into(),
+            LineErrorKind::MissingVfstype => "Missing field #3 (vfstype)".into(),
+            LineErrorKind::InvalidVfstype(ref f) => format!("Bad field #3 (vfstype) value (not UTF-8): {}", f).into(),
+            LineErrorKind::MissingMntops => "Missing field #4 (mntops)".into(),
+            LineErrorKind::InvalidMntops(ref f) => format!("Bad field #4 (mntops) value (not UTF-8): {}", f).into(),
+            LineErrorKind::MissingFreq => "Missing field #5 (freq)".into(),
+            LineErrorKind::InvalidFreq(ref f) => format!("Bad field #5 (dump) value: {}", f).into(),
+            LineErrorKind::MissingPassno => "Missing field #6 (passno)".into(),
+            LineErrorKind::InvalidPassno(ref f) => format!("Bad field #6 (passno) value: {}", f).into(),
+            LineErrorKind::MissingField(name) => format!("Missing field ({})", name).into(),
+            LineErrorKind::InvalidField(name, ref f) => format!("Bad field ({}) value: {}", name, f).into(),
         };
-        write!(out, "Line parsing: {}", desc)
+        write!(out, "{}", desc)
+    }
+}
+
+/// Error for a mount table line, located by the byte range `span` in that line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineError {
+    kind: LineErrorKind,
+    span: Range<usize>,
+}
+
+impl LineError {
+    pub fn new(kind: LineErrorKind, span: Range<usize>) -> LineError {
+        LineError {
+            kind,
+            span,
+        }
+    }
+
+    pub fn kind(&self) -> &LineErrorKind {
+        &self.kind
+    }
+
+    /// Byte range of the bad token, or an empty range where a missing field is expected
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// Print `line` with a caret under the bad token
+    pub fn render<T>(&self, line: &T) -> String where T: AsRef<[u8]> + ?Sized {
+        render_line_error(self, None, None, line.as_ref())
+    }
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "Line parsing: {}", self.kind)
+    }
+}
+
+impl Error for LineError {}
+
+/// Render a line error the rustc way, with its location and a caret under the bad token
+pub(crate) fn render_line_error(err: &LineError, source_file: Option<&Path>, line_number: Option<usize>,
+                                line: &[u8]) -> String {
+    let span = err.span();
+    let start = cmp::min(span.start, line.len());
+    let end = cmp::min(cmp::max(span.end, start), line.len());
+    // Keep tabs to align the caret with the displayed line
+    let prefix = String::from_utf8_lossy(&line[..start]);
+    let padding: String = prefix.chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+    let carets = cmp::max(String::from_utf8_lossy(&line[start..end]).chars().count(), 1);
+    let gutter = line_number.map(|nb| nb.to_string()).unwrap_or_default();
+    let blank = " ".repeat(gutter.len());
+
+    let mut out = format!("error: {}\n", err);
+    match (source_file, line_number) {
+        (Some(path), Some(nb)) => out += &format!("{} --> {}:{}:{}\n", blank, path.display(), nb, prefix.chars().count() + 1),
+        (None, Some(nb)) => out += &format!("{} --> line {}:{}\n", blank, nb, prefix.chars().count() + 1),
+        (Some(path), None) => out += &format!("{} --> {}\n", blank, path.display()),
+        (None, None) => {},
     }
+    out += &format!("{} |\n", blank);
+    out += &format!("{} | {}\n", gutter, String::from_utf8_lossy(line));
+    out += &format!("{} | {}{}", blank, padding, "^".repeat(carets));
+    out
 }
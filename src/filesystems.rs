@@ -0,0 +1,142 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use error::*;
+use parse::{MountRefIter, fields, fields_end};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::ops::Range;
+use std::str::{self, FromStr};
+
+const PROC_FILESYSTEMS: &str = "/proc/filesystems";
+
+/// Filesystem type supported by the kernel
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FsType {
+    pub name: String,
+    /// The filesystem doesn't need a block device
+    pub nodev: bool,
+}
+
+impl FsType {
+    /// Parse a line of */proc/filesystems*: an optional `nodev` marker and the type name
+    pub fn parse<T>(line: &T) -> Result<FsType, LineError> where T: AsRef<[u8]> + ?Sized {
+        let line = line.as_ref();
+        let end = fields_end(line);
+        let mut tokens = fields(line);
+        let text = |name, span: Range<usize>| str::from_utf8(&line[span.clone()]).map_err(|_|
+            LineError::new(LineErrorKind::InvalidField(name, String::from_utf8_lossy(&line[span.clone()]).into()), span));
+        let first = tokens.next()
+            .ok_or_else(|| LineError::new(LineErrorKind::MissingField("filesystem type"), end..end))?;
+        let (nodev, name) = match tokens.next() {
+            Some(span) if &line[first.clone()] == b"nodev" => (true, text("filesystem type", span)?),
+            Some(_) => {
+                let value = text("nodev", first.clone())?;
+                return Err(LineError::new(LineErrorKind::InvalidField("nodev", value.into()), first));
+            }
+            None => (false, text("filesystem type", first)?),
+        };
+        if let Some(span) = tokens.next() {
+            let value = String::from_utf8_lossy(&line[span.clone()]).into();
+            return Err(LineError::new(LineErrorKind::InvalidField("filesystem type", value), span));
+        }
+        Ok(FsType {
+            name: name.to_string(),
+            nodev,
+        })
+    }
+}
+
+impl FromStr for FsType {
+    type Err = LineError;
+
+    fn from_str(line: &str) -> Result<FsType, LineError> {
+        FsType::parse(line)
+    }
+}
+
+/// Filesystem types supported by the kernel, as listed by */proc/filesystems*
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filesystems {
+    types: Vec<FsType>,
+}
+
+impl Filesystems {
+    pub fn new<T>(reader: T) -> Result<Filesystems, ParseError> where T: BufRead {
+        Filesystems::from_lines(MountRefIter::new(reader))
+    }
+
+    fn from_lines<T>(mut lines: MountRefIter<T>) -> Result<Filesystems, ParseError> where T: BufRead {
+        let mut types = vec!();
+        while let Some(nb) = lines.next_line() {
+            let nb = nb.map_err(|e| lines.locate(From::from(e)))?;
+            let fstype = FsType::parse(lines.line())
+                .map_err(|e| lines.locate(ParseError::from_line(e, nb, lines.line())))?;
+            types.push(fstype);
+        }
+        Ok(Filesystems {
+            types,
+        })
+    }
+
+    /// Load the filesystem types supported by the running kernel
+    pub fn new_from_proc() -> Result<Filesystems, ParseError> {
+        let locate = |e| ParseError::from(e).with_source_file(PROC_FILESYSTEMS);
+        let file = File::open(PROC_FILESYSTEMS).map_err(locate)?;
+        Filesystems::from_lines(MountRefIter::new(BufReader::new(file)).with_source_file(PROC_FILESYSTEMS))
+    }
+
+    pub fn types(&self) -> &[FsType] {
+        &self.types
+    }
+
+    /// Get a filesystem type, e.g. from `MountEntry::vfstype`
+    ///
+    /// FUSE subtypes (e.g. `fuse.sshfs`) are handled by the `fuse` type.
+    pub fn get(&self, vfstype: &str) -> Option<&FsType> {
+        let base = vfstype.split('.').next().unwrap_or(vfstype);
+        self.types.iter().find(|t| t.name == base)
+    }
+
+    /// Check if the kernel supports `vfstype`, which may be a comma-separated list as in fstab
+    ///
+    /// The `auto` and `none` pseudo-types are always supported.
+    pub fn is_supported(&self, vfstype: &str) -> bool {
+        vfstype.split(',').all(|t| t == "auto" || t == "none" || self.get(t).is_some())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{Filesystems, FsType};
+
+    #[test]
+    fn test_filesystems() {
+        let fs = Filesystems::new(b"nodev\tsysfs\nnodev\tproc\n\text4\nnodev\tfuse\n\tfuseblk\n".as_ref()).unwrap();
+        assert_eq!(fs.types().len(), 5);
+        assert_eq!(fs.get("ext4"), Some(&FsType { name: "ext4".into(), nodev: false }));
+        assert!(fs.get("proc").unwrap().nodev);
+        assert!(fs.get("fuse.sshfs").unwrap().nodev);
+        assert!(fs.is_supported("ext4"));
+        assert!(fs.is_supported("auto"));
+        assert!(!fs.is_supported("xfs"));
+        assert!(!fs.is_supported("ext4,xfs"));
+
+        let err = Filesystems::new(b"\text4\nfoo\tbar\n".as_ref()).unwrap_err();
+        assert_eq!(err.line_number(), Some(2));
+        assert!("foo bar baz".parse::<FsType>().is_err());
+        assert!(Filesystems::new_from_proc().unwrap().get("proc").unwrap().nodev);
+    }
+}
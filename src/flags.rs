@@ -0,0 +1,317 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+extern crate libc;
+
+use parse::MntOps;
+use self::libc::c_ulong;
+
+// Missing from libc
+const ST_NOSYMFOLLOW: c_ulong = 0x2000;
+
+const MS_PROPAGATION: c_ulong = libc::MS_UNBINDABLE | libc::MS_PRIVATE | libc::MS_SLAVE | libc::MS_SHARED;
+
+const MS_ATIME: c_ulong = libc::MS_NOATIME | libc::MS_RELATIME | libc::MS_STRICTATIME;
+
+/// Options mapped to `mount(2)` flags: name, flags and whether the option sets or clears them
+const MS_OPTIONS: &[(&str, c_ulong, bool)] = &[
+    ("ro", libc::MS_RDONLY, true),
+    ("rw", libc::MS_RDONLY, false),
+    ("nosuid", libc::MS_NOSUID, true),
+    ("suid", libc::MS_NOSUID, false),
+    ("nodev", libc::MS_NODEV, true),
+    ("dev", libc::MS_NODEV, false),
+    ("noexec", libc::MS_NOEXEC, true),
+    ("exec", libc::MS_NOEXEC, false),
+    ("sync", libc::MS_SYNCHRONOUS, true),
+    ("async", libc::MS_SYNCHRONOUS, false),
+    ("mand", libc::MS_MANDLOCK, true),
+    ("nomand", libc::MS_MANDLOCK, false),
+    ("dirsync", libc::MS_DIRSYNC, true),
+    ("nosymfollow", libc::MS_NOSYMFOLLOW, true),
+    ("symfollow", libc::MS_NOSYMFOLLOW, false),
+    ("noatime", libc::MS_NOATIME, true),
+    ("atime", libc::MS_NOATIME, false),
+    ("nodiratime", libc::MS_NODIRATIME, true),
+    ("diratime", libc::MS_NODIRATIME, false),
+    ("relatime", libc::MS_RELATIME, true),
+    ("norelatime", libc::MS_RELATIME, false),
+    ("strictatime", libc::MS_STRICTATIME, true),
+    ("nostrictatime", libc::MS_STRICTATIME, false),
+    ("lazytime", libc::MS_LAZYTIME, true),
+    ("nolazytime", libc::MS_LAZYTIME, false),
+    ("iversion", libc::MS_I_VERSION, true),
+    ("noiversion", libc::MS_I_VERSION, false),
+    ("silent", libc::MS_SILENT, true),
+    ("loud", libc::MS_SILENT, false),
+    ("remount", libc::MS_REMOUNT, true),
+    ("bind", libc::MS_BIND, true),
+    ("rbind", libc::MS_BIND | libc::MS_REC, true),
+    ("move", libc::MS_MOVE, true),
+    ("unbindable", libc::MS_UNBINDABLE, true),
+    ("runbindable", libc::MS_UNBINDABLE | libc::MS_REC, true),
+    ("private", libc::MS_PRIVATE, true),
+    ("rprivate", libc::MS_PRIVATE | libc::MS_REC, true),
+    ("slave", libc::MS_SLAVE, true),
+    ("rslave", libc::MS_SLAVE | libc::MS_REC, true),
+    ("shared", libc::MS_SHARED, true),
+    ("rshared", libc::MS_SHARED | libc::MS_REC, true),
+];
+
+/// Options only used by mount(8) and never given to the kernel
+const USERSPACE_OPTIONS: &[&str] = &[
+    "defaults", "auto", "noauto", "user", "nouser", "users", "owner", "group", "nofail", "_netdev",
+    "loop",
+];
+
+/// Prefixes of the options only used by mount(8)
+const USERSPACE_PREFIXES: &[&str] = &["x-", "comment=", "loop=", "helper="];
+
+/// `statvfs(3)` flags and their option
+const ST_OPTIONS: &[(&str, c_ulong)] = &[
+    ("ro", libc::ST_RDONLY),
+    ("nosuid", libc::ST_NOSUID),
+    ("nodev", libc::ST_NODEV),
+    ("noexec", libc::ST_NOEXEC),
+    ("sync", libc::ST_SYNCHRONOUS),
+    ("mand", libc::ST_MANDLOCK),
+    ("noatime", libc::ST_NOATIME),
+    ("nodiratime", libc::ST_NODIRATIME),
+    ("relatime", libc::ST_RELATIME),
+    ("nosymfollow", ST_NOSYMFOLLOW),
+];
+
+/// `mount_setattr(2)` attributes and their options, setting or clearing them
+const MOUNT_ATTR_OPTIONS: &[(&str, &str, u64)] = &[
+    ("ro", "rw", libc::MOUNT_ATTR_RDONLY),
+    ("nosuid", "suid", libc::MOUNT_ATTR_NOSUID),
+    ("nodev", "dev", libc::MOUNT_ATTR_NODEV),
+    ("noexec", "exec", libc::MOUNT_ATTR_NOEXEC),
+    ("nodiratime", "diratime", libc::MOUNT_ATTR_NODIRATIME),
+    ("nosymfollow", "symfollow", libc::MOUNT_ATTR_NOSYMFOLLOW),
+];
+
+/// `mount_setattr(2)` access time modes and their option
+const MOUNT_ATTR_ATIME: &[(&str, u64)] = &[
+    ("relatime", libc::MOUNT_ATTR_RELATIME),
+    ("noatime", libc::MOUNT_ATTR_NOATIME),
+    ("strictatime", libc::MOUNT_ATTR_STRICTATIME),
+];
+
+fn is_userspace(option: &str) -> bool {
+    USERSPACE_OPTIONS.contains(&option) || USERSPACE_PREFIXES.iter().any(|p| option.starts_with(p))
+}
+
+fn ms_option(option: &str) -> Option<(c_ulong, bool)> {
+    MS_OPTIONS.iter().find(|o| o.0 == option).map(|o| (o.1, o.2))
+}
+
+/// Flags and filesystem-specific data for `mount(2)`
+///
+/// `MS_REC` applies to the whole call, so the propagation type is kept apart from the other flags
+/// and must be given to a separate `mount(2)` call, e.g. for `bind,rprivate`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MountFlags {
+    /// `MS_*` flags, with `MS_REC` only for a recursive bind
+    pub flags: c_ulong,
+    /// `MS_*` propagation type, with `MS_REC` if it is recursive
+    pub propagation: c_ulong,
+    /// Comma-separated options which are not flags
+    pub data: String,
+}
+
+impl MountFlags {
+    /// Convert mount options, the last option winning over the previous ones
+    ///
+    /// The `defaults` option is expanded and the other options only used by mount(8) (e.g. `noauto`
+    /// or `x-*`) are dropped.
+    pub fn from_mntops<'a, T>(mntops: T) -> MountFlags where T: IntoIterator<Item = &'a MntOps> {
+        let mut ret = MountFlags::default();
+        let mut data: Vec<String> = vec!();
+        for ops in mntops {
+            let option = ops.to_string();
+            match ms_option(&option) {
+                // Only one propagation type at once
+                Some((flags, true)) if flags & MS_PROPAGATION != 0 => ret.propagation = flags,
+                // Only one access time mode at once
+                Some((flags, true)) if flags & MS_ATIME != 0 => ret.flags = (ret.flags & !MS_ATIME) | flags,
+                Some((flags, true)) => ret.flags |= flags,
+                Some((flags, false)) => ret.flags &= !flags,
+                None if option == "defaults" => {
+                    for &o in ["rw", "suid", "dev", "exec", "async"].iter() {
+                        ret.flags &= !ms_option(o).map_or(0, |o| o.0);
+                    }
+                }
+                None if is_userspace(&option) => {},
+                None => data.push(option),
+            }
+        }
+        ret.data = data.join(",");
+        ret
+    }
+
+    /// Convert back to mount options, as listed in */proc/mounts*
+    pub fn to_mntops(&self) -> Vec<MntOps> {
+        let mut ret = vec!(MntOps::Write(self.flags & libc::MS_RDONLY == 0));
+        for &(option, flags, set) in MS_OPTIONS.iter().skip(2) {
+            // Recursive binds and propagation types have their own option
+            let (current, mask) = if flags & MS_PROPAGATION != 0 {
+                (self.propagation, flags | libc::MS_REC)
+            } else if flags & libc::MS_BIND != 0 {
+                (self.flags, flags | libc::MS_REC)
+            } else {
+                (self.flags, flags)
+            };
+            if set && current & mask == flags {
+                ret.push(option.parse().unwrap());
+            }
+        }
+        ret.extend(self.data.split_terminator(',').filter(|o| !o.is_empty()).map(|o| o.parse().unwrap()));
+        ret
+    }
+}
+
+/// Attributes for `mount_setattr(2)`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MountAttr {
+    /// `MOUNT_ATTR_*` attributes to set
+    pub attr_set: u64,
+    /// `MOUNT_ATTR_*` attributes to clear
+    pub attr_clr: u64,
+}
+
+impl MountAttr {
+    /// Convert mount options, ignoring the ones which are not mount attributes
+    pub fn from_mntops<'a, T>(mntops: T) -> MountAttr where T: IntoIterator<Item = &'a MntOps> {
+        let mut ret = MountAttr::default();
+        for ops in mntops {
+            let option = ops.to_string();
+            for &(set, clr, attr) in MOUNT_ATTR_OPTIONS {
+                if option == set {
+                    ret.attr_set |= attr;
+                    ret.attr_clr &= !attr;
+                } else if option == clr {
+                    ret.attr_set &= !attr;
+                    ret.attr_clr |= attr;
+                }
+            }
+            if let Some(&(_, attr)) = MOUNT_ATTR_ATIME.iter().find(|a| a.0 == option) {
+                ret.attr_set = (ret.attr_set & !libc::MOUNT_ATTR__ATIME) | attr;
+                ret.attr_clr |= libc::MOUNT_ATTR__ATIME;
+            }
+        }
+        ret
+    }
+
+    pub fn to_mntops(&self) -> Vec<MntOps> {
+        let mut ret = vec!();
+        for &(set, clr, attr) in MOUNT_ATTR_OPTIONS {
+            if self.attr_set & attr != 0 {
+                ret.push(set.parse().unwrap());
+            } else if self.attr_clr & attr != 0 {
+                ret.push(clr.parse().unwrap());
+            }
+        }
+        if self.attr_clr & libc::MOUNT_ATTR__ATIME != 0 {
+            let atime = self.attr_set & libc::MOUNT_ATTR__ATIME;
+            if let Some(&(option, _)) = MOUNT_ATTR_ATIME.iter().find(|a| a.1 == atime) {
+                ret.push(option.parse().unwrap());
+            }
+        }
+        ret
+    }
+}
+
+/// Convert the `f_flag` field of `statvfs(3)` to mount options
+pub fn mntops_from_statvfs(flags: c_ulong) -> Vec<MntOps> {
+    let mut ret = vec!(MntOps::Write(flags & libc::ST_RDONLY == 0));
+    ret.extend(ST_OPTIONS.iter().skip(1).filter(|o| flags & o.1 != 0).map(|o| o.0.parse().unwrap()));
+    ret
+}
+
+/// Convert mount options to `statvfs(3)` flags, the last option winning over the previous ones
+pub fn statvfs_from_mntops<'a, T>(mntops: T) -> c_ulong where T: IntoIterator<Item = &'a MntOps> {
+    let ms = MountFlags::from_mntops(mntops).flags;
+    ST_OPTIONS.iter()
+        .filter(|o| ms_option(o.0).map_or(false, |(f, _)| ms & f != 0))
+        .fold(0, |acc, o| acc | o.1)
+}
+
+
+#[cfg(test)]
+mod test {
+    extern crate libc;
+
+    use parse::MntOps;
+    use super::{MountAttr, MountFlags, mntops_from_statvfs, statvfs_from_mntops};
+
+    fn mntops(options: &str) -> Vec<MntOps> {
+        options.split(',').map(|o| o.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_ms_flags() {
+        let flags = MountFlags::from_mntops(&mntops("defaults,noexec,ro,rw,nosuid,noauto,x-foo,mode=755,size=1k"));
+        assert_eq!(flags, MountFlags {
+            flags: libc::MS_NOEXEC | libc::MS_NOSUID,
+            propagation: 0,
+            data: "mode=755,size=1k".into(),
+        });
+        assert_eq!(flags.to_mntops(), mntops("rw,nosuid,noexec,mode=755,size=1k"));
+        assert_eq!(MountFlags::from_mntops(&flags.to_mntops()), flags);
+
+        let flags = MountFlags::from_mntops(&mntops("ro,rbind,rprivate"));
+        assert_eq!(flags.flags, libc::MS_RDONLY | libc::MS_BIND | libc::MS_REC);
+        assert_eq!(flags.propagation, libc::MS_PRIVATE | libc::MS_REC);
+        assert_eq!(flags.to_mntops(), mntops("ro,rbind,rprivate"));
+        let flags = MountFlags::from_mntops(&mntops("bind,shared,slave"));
+        assert_eq!(flags.to_mntops(), mntops("rw,bind,slave"));
+
+        let flags = MountFlags::from_mntops(&mntops("strictatime,nodiratime,noatime"));
+        assert_eq!(flags.flags, libc::MS_NODIRATIME | libc::MS_NOATIME);
+        assert_eq!(flags.to_mntops(), mntops("rw,noatime,nodiratime"));
+        assert_eq!(MountFlags::from_mntops(&mntops("noatime,atime")).flags, 0);
+    }
+
+    #[test]
+    fn test_ms_rec() {
+        for options in ["rw,bind,rprivate", "rw,rbind,private", "rw,bind,private", "rw,rbind,rshared"].iter() {
+            let flags = MountFlags::from_mntops(&mntops(options));
+            assert_eq!(flags.to_mntops(), mntops(options));
+            assert_eq!(MountFlags::from_mntops(&flags.to_mntops()), flags);
+        }
+        let flags = MountFlags::from_mntops(&mntops("bind,rprivate"));
+        assert_eq!(flags.flags, libc::MS_BIND);
+        assert_eq!(flags.propagation, libc::MS_PRIVATE | libc::MS_REC);
+    }
+
+    #[test]
+    fn test_mount_attr() {
+        let attr = MountAttr::from_mntops(&mntops("ro,nodev,dev,noatime,data=ordered"));
+        assert_eq!(attr, MountAttr {
+            attr_set: libc::MOUNT_ATTR_RDONLY | libc::MOUNT_ATTR_NOATIME,
+            attr_clr: libc::MOUNT_ATTR_NODEV | libc::MOUNT_ATTR__ATIME,
+        });
+        assert_eq!(attr.to_mntops(), mntops("ro,dev,noatime"));
+        assert_eq!(MountAttr::from_mntops(&attr.to_mntops()), attr);
+    }
+
+    #[test]
+    fn test_statvfs() {
+        let flags = libc::ST_RDONLY | libc::ST_NOSUID | libc::ST_RELATIME;
+        assert_eq!(mntops_from_statvfs(flags), mntops("ro,nosuid,relatime"));
+        assert_eq!(statvfs_from_mntops(&mntops_from_statvfs(flags)), flags);
+        assert_eq!(statvfs_from_mntops(&mntops("rw,noexec,noexec,mode=755")), libc::ST_NOEXEC);
+    }
+}
@@ -0,0 +1,168 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use filesystems::Filesystems;
+use parse::MountEntry;
+
+/// Known filesystem types and their class
+const FS_CLASSES: &[(&str, FsClass)] = &[
+    ("afs", FsClass::Network),
+    ("autofs", FsClass::Autofs),
+    ("bcachefs", FsClass::BlockBacked),
+    ("binder", FsClass::Pseudo),
+    ("binfmt_misc", FsClass::Pseudo),
+    ("bpf", FsClass::Pseudo),
+    ("btrfs", FsClass::BlockBacked),
+    ("ceph", FsClass::Network),
+    ("cgroup", FsClass::Pseudo),
+    ("cgroup2", FsClass::Pseudo),
+    ("cifs", FsClass::Network),
+    ("coda", FsClass::Network),
+    ("configfs", FsClass::Pseudo),
+    ("cpuset", FsClass::Pseudo),
+    ("davfs", FsClass::Network),
+    ("debugfs", FsClass::Pseudo),
+    ("devpts", FsClass::Pseudo),
+    ("devtmpfs", FsClass::Pseudo),
+    ("efivarfs", FsClass::Pseudo),
+    ("erofs", FsClass::BlockBacked),
+    ("exfat", FsClass::BlockBacked),
+    ("ext2", FsClass::BlockBacked),
+    ("ext3", FsClass::BlockBacked),
+    ("ext4", FsClass::BlockBacked),
+    ("f2fs", FsClass::BlockBacked),
+    ("fuse", FsClass::Fuse),
+    ("fuseblk", FsClass::Fuse),
+    ("fusectl", FsClass::Pseudo),
+    ("gfs2", FsClass::BlockBacked),
+    ("glusterfs", FsClass::Network),
+    ("hfsplus", FsClass::BlockBacked),
+    ("hugetlbfs", FsClass::Memory),
+    ("iso9660", FsClass::BlockBacked),
+    ("jfs", FsClass::BlockBacked),
+    ("lustre", FsClass::Network),
+    ("mergerfs", FsClass::Fuse),
+    ("minix", FsClass::BlockBacked),
+    ("mqueue", FsClass::Pseudo),
+    ("msdos", FsClass::BlockBacked),
+    ("ncpfs", FsClass::Network),
+    ("nfs", FsClass::Network),
+    ("nfs4", FsClass::Network),
+    ("nilfs2", FsClass::BlockBacked),
+    ("nsfs", FsClass::Pseudo),
+    ("ntfs", FsClass::BlockBacked),
+    ("ntfs-3g", FsClass::Fuse),
+    ("ntfs3", FsClass::BlockBacked),
+    ("ocfs2", FsClass::BlockBacked),
+    ("orangefs", FsClass::Network),
+    ("overlay", FsClass::Overlay),
+    ("proc", FsClass::Pseudo),
+    ("pstore", FsClass::Pseudo),
+    ("ramfs", FsClass::Memory),
+    ("reiserfs", FsClass::BlockBacked),
+    ("rpc_pipefs", FsClass::Pseudo),
+    ("s3fs", FsClass::Fuse),
+    ("securityfs", FsClass::Pseudo),
+    ("selinuxfs", FsClass::Pseudo),
+    ("smb3", FsClass::Network),
+    ("smbfs", FsClass::Network),
+    ("squashfs", FsClass::BlockBacked),
+    ("sshfs", FsClass::Fuse),
+    ("swap", FsClass::BlockBacked),
+    ("sysfs", FsClass::Pseudo),
+    ("tmpfs", FsClass::Memory),
+    ("tracefs", FsClass::Pseudo),
+    ("udf", FsClass::BlockBacked),
+    ("vfat", FsClass::BlockBacked),
+    ("virtiofs", FsClass::Other),
+    ("xfs", FsClass::BlockBacked),
+    ("zfs", FsClass::BlockBacked),
+    ("zonefs", FsClass::BlockBacked),
+    ("9p", FsClass::Network),
+];
+
+/// Kind of storage behind a filesystem type
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FsClass {
+    /// Kernel interface without any storage (e.g. `proc`, `sysfs`)
+    Pseudo,
+    /// Volatile storage in memory (e.g. `tmpfs`)
+    Memory,
+    BlockBacked,
+    Network,
+    Fuse,
+    Overlay,
+    Autofs,
+    /// Anything else, e.g. `virtiofs` shared by a hypervisor or a type unknown to the table
+    Other,
+}
+
+impl FsClass {
+    /// Get the class of a filesystem type from the built-in table
+    pub fn from_known(vfstype: &str) -> Option<FsClass> {
+        if vfstype.starts_with("fuse.") {
+            return Some(FsClass::Fuse);
+        }
+        FS_CLASSES.iter().find(|&&(t, _)| t == vfstype).map(|&(_, class)| class)
+    }
+
+    /// Get the class of a filesystem type, e.g. with `Filesystems::new_from_proc()`
+    ///
+    /// Types missing from the built-in table are block-backed if `filesystems` lists them without
+    /// the `nodev` marker, and `Other` otherwise.
+    pub fn from_vfstype(vfstype: &str, filesystems: &Filesystems) -> FsClass {
+        match FsClass::from_known(vfstype) {
+            Some(class) => class,
+            None => match filesystems.get(vfstype) {
+                Some(t) if !t.nodev => FsClass::BlockBacked,
+                _ => FsClass::Other,
+            },
+        }
+    }
+}
+
+impl MountEntry {
+    /// Get the class of the filesystem type, as with `FsClass::from_vfstype()`
+    pub fn fs_class(&self, filesystems: &Filesystems) -> FsClass {
+        FsClass::from_vfstype(&self.vfstype, filesystems)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use filesystems::Filesystems;
+    use parse::MountEntry;
+    use super::FsClass;
+
+    #[test]
+    fn test_fs_class() {
+        let fs = Filesystems::new(b"nodev\tproc\n\tfoofs\nnodev\tbarfs\n".as_ref()).unwrap();
+        let class = |line: &str| line.parse::<MountEntry>().unwrap().fs_class(&fs);
+        assert_eq!(class("proc /proc proc rw 0 0"), FsClass::Pseudo);
+        assert_eq!(class("tmpfs /tmp tmpfs rw 0 0"), FsClass::Memory);
+        assert_eq!(class("/dev/sda1 / ext4 rw 0 1"), FsClass::BlockBacked);
+        assert_eq!(class("server:/export /mnt nfs4 rw 0 0"), FsClass::Network);
+        assert_eq!(class("sshfs#host: /mnt fuse.sshfs rw 0 0"), FsClass::Fuse);
+        assert_eq!(class("overlay /merged overlay rw 0 0"), FsClass::Overlay);
+        assert_eq!(class("systemd-1 /boot autofs rw 0 0"), FsClass::Autofs);
+        assert_eq!(class("tank/home /home zfs rw 0 0"), FsClass::BlockBacked);
+        assert_eq!(class("share /mnt virtiofs rw 0 0"), FsClass::Other);
+        assert_eq!(class("host:/srv /mnt glusterfs rw 0 0"), FsClass::Network);
+        assert_eq!(FsClass::from_known("foofs"), None);
+        assert_eq!(FsClass::from_vfstype("foofs", &fs), FsClass::BlockBacked);
+        assert_eq!(FsClass::from_vfstype("barfs", &fs), FsClass::Other);
+        assert_eq!(FsClass::from_vfstype("bazfs", &fs), FsClass::Other);
+    }
+}
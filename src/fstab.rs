@@ -0,0 +1,282 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use error::*;
+use parse::{MountEntry, MountEntryRef, fields};
+use std::fmt;
+use std::fs;
+use std::iter;
+use std::path::Path;
+
+/// Entry line of an fstab document
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct EntryLine {
+    /// Raw line giving the indentation and the column alignment
+    template: Vec<u8>,
+    /// Parsed entry, if `template` is the line of this entry
+    original: Option<MountEntry>,
+    entry: MountEntry,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Line {
+    /// Comment, blank or unparseable line
+    Raw(Vec<u8>),
+    Entry(Box<EntryLine>),
+}
+
+/// Line terminators, the last line possibly having none
+const LF: &[u8] = b"\n";
+const CRLF: &[u8] = b"\r\n";
+const NO_EOL: &[u8] = b"";
+
+/// Format `entry` like the line `template`
+///
+/// Tab separators are kept as is, while space separators are adjusted to keep the following
+/// fields at the same column, or replaced with a single space where a field doesn't fit.
+fn format_like(entry: &MountEntry, template: &[u8]) -> Vec<u8> {
+    let spans: Vec<_> = fields(template).collect();
+    let mut ret = match spans.first() {
+        Some(span) => template[..span.start].to_vec(),
+        None => vec!(),
+    };
+    let values = entry.to_fields();
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            match (spans.get(i - 1), spans.get(i)) {
+                (Some(prev), Some(next)) if template[prev.end..next.start].contains(&b'\t') => {
+                    ret.extend_from_slice(&template[prev.end..next.start]);
+                }
+                (_, Some(next)) => {
+                    let pad = next.start.saturating_sub(ret.len()).max(1);
+                    ret.extend(iter::repeat(b' ').take(pad));
+                }
+                _ => ret.push(b' '),
+            }
+        }
+        ret.extend_from_slice(value);
+    }
+    ret
+}
+
+/// Lossless fstab model, to edit entries while keeping comments and formatting
+///
+/// Lines which are not modified are written back exactly as they were read, with their `\n` or
+/// `\r\n` terminator.  Swap entries are editable too, their `file` being e.g. `none`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FstabDocument {
+    /// Lines with their terminator
+    lines: Vec<(Line, &'static [u8])>,
+}
+
+impl FstabDocument {
+    pub fn parse<T>(content: T) -> FstabDocument where T: AsRef<[u8]> {
+        let mut rest = content.as_ref();
+        let mut lines = vec!();
+        while !rest.is_empty() {
+            let (line, eol) = match rest.iter().position(|&c| c == b'\n') {
+                Some(i) if i > 0 && rest[i - 1] == b'\r' => (&rest[..i - 1], CRLF),
+                Some(i) => (&rest[..i], LF),
+                None => (rest, NO_EOL),
+            };
+            rest = &rest[line.len() + eol.len()..];
+            // Commented out entries may be parseable
+            let is_comment = line.iter().find(|c| !c.is_ascii_whitespace()) == Some(&b'#');
+            let line = match MountEntryRef::parse_fstab(line) {
+                Ok(entry) if !is_comment => Line::Entry(Box::new(EntryLine {
+                    template: line.to_vec(),
+                    original: Some(entry.clone().into_owned()),
+                    entry: entry.into_owned(),
+                })),
+                _ => Line::Raw(line.to_vec()),
+            };
+            lines.push((line, eol));
+        }
+        FstabDocument {
+            lines,
+        }
+    }
+
+    /// Read and parse the fstab file `path`
+    pub fn open<T>(path: T) -> Result<FstabDocument, ParseError> where T: AsRef<Path> {
+        fs::read(&path).map(FstabDocument::parse)
+            .map_err(|e| ParseError::from(e).with_source_file(path.as_ref()))
+    }
+
+    fn entry_lines(&self) -> impl Iterator<Item = &EntryLine> {
+        self.lines.iter().filter_map(|l| match l.0 {
+            Line::Entry(ref e) => Some(&**e),
+            Line::Raw(_) => None,
+        })
+    }
+
+    /// Parsed entries, in the file order
+    pub fn entries(&self) -> impl Iterator<Item = &MountEntry> {
+        self.entry_lines().map(|e| &e.entry)
+    }
+
+    /// Entries to modify in place, the modified ones being reformatted
+    pub fn entries_mut(&mut self) -> impl Iterator<Item = &mut MountEntry> {
+        self.lines.iter_mut().filter_map(|l| match l.0 {
+            Line::Entry(ref mut e) => Some(&mut e.entry),
+            Line::Raw(_) => None,
+        })
+    }
+
+    /// Get the first entry mounted on `file`
+    pub fn get<T>(&self, file: T) -> Option<&MountEntry> where T: AsRef<Path> {
+        self.entries().find(|m| m.file == file.as_ref())
+    }
+
+    /// Get the first entry mounted on `file` to modify it
+    pub fn get_mut<T>(&mut self, file: T) -> Option<&mut MountEntry> where T: AsRef<Path> {
+        self.entries_mut().find(|m| m.file == file.as_ref())
+    }
+
+    /// Append an entry, aligned with the last one and with the same line terminators
+    pub fn push(&mut self, entry: MountEntry) {
+        let template = self.entry_lines().last().map(|e| e.template.clone()).unwrap_or_default();
+        let eol = self.lines.iter().map(|l| l.1).find(|eol| !eol.is_empty()).unwrap_or(LF);
+        // Keep a missing final terminator missing
+        let eol = match self.lines.last_mut() {
+            Some(last) if last.1.is_empty() => {
+                last.1 = eol;
+                NO_EOL
+            }
+            _ => eol,
+        };
+        self.lines.push((Line::Entry(Box::new(EntryLine {
+            template,
+            original: None,
+            entry,
+        })), eol));
+    }
+
+    /// Remove all the entries mounted on `file`
+    pub fn remove<T>(&mut self, file: T) -> Vec<MountEntry> where T: AsRef<Path> {
+        let mut removed = vec!();
+        self.lines.retain(|l| match l.0 {
+            Line::Entry(ref e) if e.entry.file == file.as_ref() => {
+                removed.push(e.entry.clone());
+                false
+            }
+            _ => true,
+        });
+        removed
+    }
+
+    /// Serialize the document, keeping the untouched lines as they were
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = vec!();
+        for &(ref line, eol) in self.lines.iter() {
+            match *line {
+                Line::Raw(ref raw) => ret.extend_from_slice(raw),
+                Line::Entry(ref e) if e.original.as_ref() == Some(&e.entry) => ret.extend_from_slice(&e.template),
+                Line::Entry(ref e) => ret.extend(format_like(&e.entry, &e.template)),
+            }
+            ret.extend_from_slice(eol);
+        }
+        ret
+    }
+
+    /// Write the document to the file `path`
+    pub fn save<T>(&self, path: T) -> Result<(), ParseError> where T: AsRef<Path> {
+        fs::write(&path, self.to_bytes()).map_err(|e| ParseError::from(e).with_source_file(path.as_ref()))
+    }
+}
+
+/// Format the document, the non UTF-8 bytes being lossy unlike with `to_bytes()`
+impl fmt::Display for FstabDocument {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "{}", String::from_utf8_lossy(&self.to_bytes()))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use parse::{MntOps, MountEntry};
+    use std::path::Path;
+    use super::FstabDocument;
+
+    const FSTAB: &str = "\
+# <file system>  <mount point>  <type>  <options>  <dump>  <pass>
+
+# Admin note: keep /srv last
+UUID=1234        /              ext4    defaults   0       1
+/dev/sda2\t/home\text4\tdefaults\t0\t2
+/dev/sda3        none           swap    sw         0       0
+#/dev/sdb1       /old           ext4    defaults   0       2
+/dev/sdc1        /srv           xfs     noatime    0       2
+";
+
+    #[test]
+    fn test_lossless() {
+        let doc = FstabDocument::parse(FSTAB);
+        assert_eq!(doc.to_string(), FSTAB);
+        assert_eq!(doc.entries().count(), 4);
+        assert_eq!(FstabDocument::parse("").to_string(), "");
+        assert_eq!(FstabDocument::parse("# no newline").to_string(), "# no newline");
+    }
+
+    #[test]
+    fn test_swap_crlf() {
+        let mut doc = FstabDocument::parse("# swap\r\n/dev/sda3 none swap sw 0 0\r\n/dev/sda1 / ext4 defaults 0 1");
+        let swap = doc.entries_mut().find(|m| m.vfstype == "swap").unwrap();
+        swap.mntops.push(MntOps::Extra("pri=10".into()));
+        doc.push("/dev/sda2 /home ext4 defaults 0 2".parse::<MountEntry>().unwrap());
+        assert_eq!(doc.to_bytes(), b"# swap\r\n/dev/sda3 none swap sw,pri=10 0 0\r\n\
+            /dev/sda1 / ext4 defaults 0 1\r\n/dev/sda2 /home ext4 defaults 0 2".to_vec());
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut doc = FstabDocument::parse(FSTAB);
+        doc.get_mut("/").unwrap().mntops.push(MntOps::Extra("errors=remount-ro".into()));
+        doc.get_mut("/home").unwrap().mntops = vec!(MntOps::Exec(false));
+        assert_eq!(doc.remove("/srv").len(), 1);
+        doc.push("LABEL=data /data ext4 defaults 0 2".parse::<MountEntry>().unwrap());
+        assert_eq!(doc.get("/data").unwrap().spec, "LABEL=data");
+        assert!(doc.get(Path::new("/srv")).is_none());
+        assert_eq!(doc.to_string(), "\
+# <file system>  <mount point>  <type>  <options>  <dump>  <pass>
+
+# Admin note: keep /srv last
+UUID=1234        /              ext4    defaults,errors=remount-ro 0 1
+/dev/sda2\t/home\text4\tnoexec\t0\t2
+/dev/sda3        none           swap    sw         0       0
+#/dev/sdb1       /old           ext4    defaults   0       2
+LABEL=data       /data          ext4    defaults   0       2
+");
+    }
+
+    #[test]
+    fn test_push_aligned() {
+        let mut doc = FstabDocument::parse("/dev/sda1  /  ext4  defaults  0  1\n");
+        doc.push("/dev/sda2 /home ext4 noatime 0 2".parse::<MountEntry>().unwrap());
+        assert_eq!(doc.to_string(), "\
+/dev/sda1  /  ext4  defaults  0  1
+/dev/sda2  /home ext4 noatime 0  2
+");
+        let mut doc = FstabDocument::parse("/dev/sda1        /              ext4    defaults   0       1\n");
+        doc.push("LABEL=datadisk /data ext4 defaults 0 2".parse::<MountEntry>().unwrap());
+        assert_eq!(doc.to_string(), "\
+/dev/sda1        /              ext4    defaults   0       1
+LABEL=datadisk   /data          ext4    defaults   0       2
+");
+        let mut doc = FstabDocument::default();
+        doc.push("/dev/sda1 / ext4 defaults 0 1".parse::<MountEntry>().unwrap());
+        assert_eq!(doc.to_string(), "/dev/sda1 / ext4 defaults 0 1\n");
+    }
+}
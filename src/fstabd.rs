@@ -0,0 +1,227 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use error::*;
+use lenient::LineDiagnostic;
+use parse::{MountEntry, MountIter};
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Location of an fstab line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryOrigin {
+    pub source_file: PathBuf,
+    /// Line number, starting from 1
+    pub line_number: usize,
+}
+
+impl fmt::Display for EntryOrigin {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "{}:{}", self.source_file.display(), self.line_number)
+    }
+}
+
+/// Entry of a merged fstab, along with the line it comes from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FstabEntry {
+    pub entry: MountEntry,
+    pub origin: EntryOrigin,
+}
+
+/// Entry replaced by a later one for the same target
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OverrideWarning {
+    /// Target, or source of a swap entry
+    pub target: PathBuf,
+    pub overridden: EntryOrigin,
+    pub by: EntryOrigin,
+}
+
+impl fmt::Display for OverrideWarning {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "{}: entry for {} overridden by {}", self.overridden, self.target.display(), self.by)
+    }
+}
+
+/// Result of merging an fstab with its drop-in fragments
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergedFstab {
+    /// Entries in definition order, an overriding entry taking the place of the overridden one
+    pub entries: Vec<FstabEntry>,
+    pub warnings: Vec<OverrideWarning>,
+    /// Unparseable lines, with their file
+    pub skipped: Vec<(PathBuf, LineDiagnostic)>,
+}
+
+/// Get what identifies an fstab entry, the source of a swap entry which has no target
+fn entry_key(entry: &MountEntry) -> (bool, &Path) {
+    if entry.vfstype == "swap" {
+        (true, Path::new(&entry.spec))
+    } else {
+        (false, &entry.file)
+    }
+}
+
+impl MergedFstab {
+    fn add_file(&mut self, path: &Path) -> Result<(), ParseError> {
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(ParseError::from(e).with_source_file(path)),
+        };
+        let mut entries = MountIter::new(BufReader::new(file)).with_source_file(path).skip_comments().lenient();
+        while let Some(entry) = entries.next() {
+            let entry = entry?;
+            let origin = EntryOrigin {
+                source_file: path.to_path_buf(),
+                line_number: entries.line_number(),
+            };
+            match self.entries.iter_mut().find(|e| entry_key(&e.entry) == entry_key(&entry)) {
+                Some(previous) => {
+                    self.warnings.push(OverrideWarning {
+                        target: entry_key(&entry).1.to_path_buf(),
+                        overridden: previous.origin.clone(),
+                        by: origin.clone(),
+                    });
+                    *previous = FstabEntry {
+                        entry,
+                        origin,
+                    };
+                }
+                None => self.entries.push(FstabEntry {
+                    entry,
+                    origin,
+                }),
+            }
+        }
+        self.skipped.extend(entries.into_diagnostics().into_iter().map(|d| (path.to_path_buf(), d)));
+        Ok(())
+    }
+
+    /// Merge */etc/fstab* and its fragments, `host_path` giving the file to read for a path
+    pub(crate) fn load_with<F>(host_path: F) -> Result<MergedFstab, ParseError>
+            where F: Fn(&Path) -> io::Result<PathBuf> {
+        let map = |path: &Path| host_path(path).map_err(|e| ParseError::access(path, e));
+        let mut merged = MergedFstab::default();
+        merged.add_file(&map(Path::new("/etc/fstab"))?)?;
+        for name in fragments(&map(Path::new("/etc/fstab.d"))?)? {
+            merged.add_file(&map(&Path::new("/etc/fstab.d").join(name))?)?;
+        }
+        Ok(merged)
+    }
+}
+
+/// Get the file names of the fragments in `dir`, sorted
+fn fragments(dir: &Path) -> Result<Vec<OsString>, ParseError> {
+    let locate = |e| ParseError::from(e).with_source_file(dir);
+    let mut ret = vec!();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(ret),
+        Err(e) => return Err(locate(e)),
+    };
+    for entry in entries {
+        let name = entry.map_err(locate)?.file_name();
+        if Path::new(&name).extension().map_or(false, |e| e == "fstab") {
+            ret.push(name);
+        }
+    }
+    ret.sort();
+    Ok(ret)
+}
+
+/// Load */etc/fstab* and the */etc/fstab.d/\*.fstab* fragments, in file name order
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FstabLoader {
+    root: PathBuf,
+}
+
+impl Default for FstabLoader {
+    fn default() -> FstabLoader {
+        FstabLoader::new()
+    }
+}
+
+impl FstabLoader {
+    pub fn new() -> FstabLoader {
+        FstabLoader {
+            root: PathBuf::from("/"),
+        }
+    }
+
+    /// Read the files below `root` instead of */*, e.g. for an offline image
+    pub fn with_root<T>(mut self, root: T) -> FstabLoader where T: Into<PathBuf> {
+        self.root = root.into();
+        self
+    }
+
+    /// Merge the fstab files, a missing file or directory being considered empty
+    pub fn load(&self) -> Result<MergedFstab, ParseError> {
+        MergedFstab::load_with(|path| Ok(self.root.join(path.strip_prefix("/").unwrap_or(path))))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::process;
+    use super::FstabLoader;
+
+    #[test]
+    fn test_fstab_loader() {
+        let root = env::temp_dir().join(format!("mnt-fstabd-{}", process::id()));
+        fs::create_dir_all(root.join("etc/fstab.d")).unwrap();
+        fs::write(root.join("etc/fstab"), "\
+            /dev/sda1 / ext4 defaults 0 1\n\
+            /dev/sda2 none swap sw 0 0\n\
+            /dev/sda3 /home ext4 defaults 0 2\n\
+        ").unwrap();
+        fs::write(root.join("etc/fstab.d/20-home.fstab"), "\
+            /dev/sdb1 /home xfs noatime 0 2\n\
+            /dev/sda2 none swap sw,pri=10 0 0\n\
+        ").unwrap();
+        fs::write(root.join("etc/fstab.d/10-srv.fstab"), "\
+            # Data\n\
+            /dev/sdc1 /srv ext4 defaults 0 2\n\
+            /dev/sdc2 none swap sw 0 0\n\
+        ").unwrap();
+        fs::write(root.join("etc/fstab.d/ignored.conf"), "/dev/sdd1 /srv ext4 defaults 0 2\n").unwrap();
+        let merged = FstabLoader::new().with_root(&root).load();
+        let empty = FstabLoader::new().with_root(root.join("nonexistent")).load();
+        fs::remove_dir_all(&root).unwrap();
+
+        let merged = merged.unwrap();
+        let targets: Vec<_> = merged.entries.iter().map(|e| (e.entry.file.to_str().unwrap(), e.origin.to_string())).collect();
+        assert_eq!(targets, vec!(
+            ("/", format!("{}:1", root.join("etc/fstab").display())),
+            ("none", format!("{}:2", root.join("etc/fstab.d/20-home.fstab").display())),
+            ("/home", format!("{}:1", root.join("etc/fstab.d/20-home.fstab").display())),
+            ("/srv", format!("{}:2", root.join("etc/fstab.d/10-srv.fstab").display())),
+            ("none", format!("{}:3", root.join("etc/fstab.d/10-srv.fstab").display())),
+        ));
+        assert_eq!(merged.warnings.len(), 2);
+        assert_eq!(merged.warnings[0].target, Path::new("/home"));
+        assert_eq!(merged.warnings[0].overridden.line_number, 3);
+        assert_eq!(merged.warnings[1].target, Path::new("/dev/sda2"));
+        assert_eq!(merged.entries[1].entry.spec, "/dev/sda2");
+        assert!(merged.skipped.is_empty());
+        assert!(empty.unwrap().entries.is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use error::*;
+use fsclass::FsClass;
+use mountinfo::{MountInfoEntry, MountTable};
+use parse::{DumpField, MntOps, MountEntry};
+use source::{DeviceResolver, MountSource};
+use std::ffi::OsString;
+use std::path::Path;
+
+/// Directories of the API filesystems set up by the init system
+const API_DIRS: &[&str] = &["/dev", "/proc", "/run", "/sys"];
+
+/// Options shown by the kernel which should not be set in an fstab, per filesystem type
+///
+/// Options ending with `=` match any value, which comes from the filesystem.
+const KERNEL_ONLY_OPTIONS: &[(&str, &[&str])] = &[
+    ("btrfs", &["subvolid="]),
+    ("xfs", &["attr2", "inode64", "largeio", "logbsize=", "logbufs=", "noquota", "sunit=", "swalloc", "swidth="]),
+];
+
+/// Filesystem types checked by `fsck(8)` at boot, the others having a no-op checker or none
+const FSCK_TYPES: &[&str] = &["exfat", "ext2", "ext3", "ext4", "f2fs", "jfs", "msdos", "reiserfs", "vfat"];
+
+fn is_kernel_only(vfstype: &str, ops: &MntOps) -> bool {
+    let extra = match *ops {
+        MntOps::Extra(ref extra) => extra,
+        _ => return false,
+    };
+    let options = KERNEL_ONLY_OPTIONS.iter().find(|o| o.0 == vfstype).map_or(&[][..], |o| o.1);
+    // The SELinux label support is shown for any filesystem type
+    extra == "seclabel" || options.iter().any(|o| if o.ends_with('=') { extra.starts_with(o) } else { extra == o })
+}
+
+/// Get the preferred tag for a device path
+fn device_tag(resolver: &DeviceResolver, source: &OsString) -> Option<OsString> {
+    match MountSource::parse(source) {
+        MountSource::Device(ref path) if path.starts_with("/dev") => {}
+        _ => return None,
+    }
+    let tags = resolver.tags(source).ok()?;
+    let uuid = tags.iter().find(|t| matches!(**t, MountSource::Uuid(_)));
+    let label = tags.iter().find(|t| matches!(**t, MountSource::Label(_)));
+    uuid.or(label).map(|t| t.to_string().into())
+}
+
+/// Get the fstab entry of a live mount, or `None` for a bind mount whose origin isn't mounted
+fn to_fstab_entry(table: &MountTable, resolver: &DeviceResolver, live: &MountInfoEntry) -> Option<MountEntry> {
+    if live.is_bind(table) {
+        let origin = live.bind_origin(table)?;
+        let mut mntops = vec!(MntOps::Extra("bind".into()));
+        mntops.extend(live.mntops.iter().filter(|o| !is_kernel_only(&live.vfstype, o)).cloned());
+        return Some(MountEntry {
+            spec: origin.into_os_string(),
+            file: live.mount_point.clone(),
+            vfstype: "none".into(),
+            mntops,
+            freq: DumpField::Ignore,
+            passno: None,
+        });
+    }
+    let mut entry = live.to_mount_entry();
+    let vfstype = entry.vfstype.clone();
+    entry.mntops.retain(|o| !is_kernel_only(&vfstype, o));
+    entry.spec = device_tag(resolver, &live.source).unwrap_or(entry.spec);
+    entry.passno = if !FSCK_TYPES.contains(&entry.vfstype.as_str()) {
+        None
+    } else if entry.file == Path::new("/") {
+        Some(1)
+    } else {
+        Some(2)
+    };
+    Some(entry)
+}
+
+/// Get the `table` mounts which belong in an fstab
+///
+/// Pseudo and autofs filesystems are skipped, as well as the mounts of the API directories (e.g.
+/// the `tmpfs` of */dev/shm* or */run*) and the mounts hidden by a later one on the same target.
+pub(crate) fn fstab_mounts(table: &MountTable) -> impl Iterator<Item = &MountInfoEntry> {
+    let entries = table.entries();
+    entries.iter().enumerate()
+        .filter(move |&(i, m)| !entries[i + 1..].iter().any(|o| o.mount_point == m.mount_point))
+        .map(|(_, m)| m)
+        .filter(|m| !API_DIRS.iter().any(|d| m.mount_point.starts_with(d)))
+        .filter(|m| !matches!(FsClass::from_known(&m.vfstype), Some(FsClass::Pseudo) | Some(FsClass::Autofs)))
+}
+
+/// Generate fstab entries from the `table` mounts
+///
+/// Pseudo, autofs and API filesystems are skipped, as well as the mounts hidden by another one.
+/// Device paths are replaced with their `UUID=` or `LABEL=` tag and bind mounts are kept as `bind`
+/// entries, unless their origin isn't mounted anymore.  Only the types checked by `fsck(8)` get
+/// a `passno`.
+pub fn genfstab(table: &MountTable, resolver: &DeviceResolver) -> Vec<MountEntry> {
+    fstab_mounts(table).filter_map(|m| to_fstab_entry(table, resolver, m)).collect()
+}
+
+/// Generate fstab entries from a snapshot of */proc/self/mountinfo*
+pub fn genfstab_from_proc() -> Result<Vec<MountEntry>, ParseError> {
+    Ok(genfstab(&MountTable::new_from_proc()?, &DeviceResolver::new()))
+}
+
+
+#[cfg(test)]
+mod test {
+    use mountinfo::MountTable;
+    use parse::MntOps;
+    use source::DeviceResolver;
+    use super::{genfstab, is_kernel_only};
+
+    const MOUNTINFO: &[u8] = b"\
+        22 1 254:0 / / rw,relatime - ext4 /dev/nonexistent/root rw,seclabel,errors=remount-ro\n\
+        23 22 0:21 / /proc rw,nosuid,nodev,noexec,relatime - proc proc rw\n\
+        24 22 0:22 / /run rw,nosuid,nodev - tmpfs tmpfs rw,mode=755\n\
+        25 22 0:23 / /tmp rw,nosuid,nodev - tmpfs tmpfs rw,inode64\n\
+        26 22 8:1 / /boot rw,relatime - vfat /dev/nonexistent/boot rw\n\
+        30 22 254:1 / /srv\\040data rw,noatime - xfs /dev/nonexistent/srv rw,attr2,inode64,logbufs=8,logbsize=32k,noquota\n\
+        40 22 254:1 /www /var/www ro,noatime - xfs /dev/nonexistent/srv rw\n\
+        41 22 0:40 / /net autofs rw,relatime - autofs systemd-1 rw\n\
+        42 22 254:2 /hidden /var/hidden rw,relatime - ext4 /dev/nonexistent/hidden rw\n\
+    ";
+
+    #[test]
+    fn test_genfstab() {
+        let table = MountTable::from_fixture(MOUNTINFO);
+        let resolver = DeviceResolver::new().with_disk_dir("/nonexistent");
+        let fstab: Vec<String> = genfstab(&table, &resolver).iter().map(|m| m.to_string()).collect();
+        assert_eq!(fstab, vec!(
+            "/dev/nonexistent/root / ext4 rw,relatime,errors=remount-ro 0 1",
+            "tmpfs /tmp tmpfs rw,nosuid,nodev,inode64 0 0",
+            "/dev/nonexistent/boot /boot vfat rw,relatime 0 2",
+            "/dev/nonexistent/srv /srv\\040data xfs rw,noatime 0 0",
+            "/srv\\040data/www /var/www none bind,ro,noatime 0 0",
+        ));
+    }
+
+    #[test]
+    fn test_kernel_only() {
+        let kernel_only = |t, o: &str| is_kernel_only(t, &o.parse::<MntOps>().unwrap());
+        assert!(kernel_only("xfs", "inode64") && kernel_only("xfs", "logbsize=32k") && kernel_only("btrfs", "subvolid=5"));
+        assert!(kernel_only("ext4", "seclabel") && !kernel_only("tmpfs", "inode64") && !kernel_only("ext4", "subvolid=5"));
+        assert!(!kernel_only("xfs", "noatime") && !kernel_only("btrfs", "subvol=/@home"));
+    }
+}
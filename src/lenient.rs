@@ -0,0 +1,161 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use error::*;
+use parse::{MountEntry, MountIter, MountRefIter, get_mount_from, get_submounts_from};
+use std::fmt;
+use std::io::BufRead;
+use std::path::Path;
+
+/// Unparseable line skipped by a `LenientMountIter`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineDiagnostic {
+    /// Line number, starting from 1
+    pub line_number: usize,
+    /// Raw content of the line, without the trailing newline
+    pub line: Vec<u8>,
+    pub error: LineError,
+}
+
+impl LineDiagnostic {
+    /// Print the skipped line with a caret under the bad token
+    pub fn render(&self) -> String {
+        render_line_error(&self.error, None, Some(self.line_number), &self.line)
+    }
+}
+
+impl fmt::Display for LineDiagnostic {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "Skipped line {} (`{}`): {}", self.line_number,
+               String::from_utf8_lossy(&self.line), self.error)
+    }
+}
+
+/// Iterator over the parseable entries of a mount table
+///
+/// Bad lines are skipped and recorded as diagnostics while I/O errors are still returned.
+pub struct LenientMountIter<T> {
+    entries: MountRefIter<T>,
+    diagnostics: Vec<LineDiagnostic>,
+}
+
+impl<T> LenientMountIter<T> where T: BufRead {
+    /// Number of the last read line, i.e. of the last returned entry, starting from 1
+    pub fn line_number(&self) -> usize {
+        self.entries.line_number()
+    }
+}
+
+impl<T> LenientMountIter<T> {
+    /// Diagnostics for the lines skipped so far
+    pub fn diagnostics(&self) -> &[LineDiagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn into_diagnostics(self) -> Vec<LineDiagnostic> {
+        self.diagnostics
+    }
+}
+
+impl<T> MountIter<T> where T: BufRead {
+    /// Keep going past unparseable lines instead of returning an error for them
+    pub fn lenient(self) -> LenientMountIter<T> {
+        LenientMountIter {
+            entries: self.entries,
+            diagnostics: vec!(),
+        }
+    }
+}
+
+impl<T> Iterator for LenientMountIter<T> where T: BufRead {
+    type Item = Result<MountEntry, ParseError>;
+
+    fn next(&mut self) -> Option<<Self as Iterator>::Item> {
+        loop {
+            let line_number = match self.entries.next_line()? {
+                Ok(nb) => nb,
+                Err(e) => return Some(Err(self.entries.locate(From::from(e)))),
+            };
+            match self.entries.parse_line() {
+                Ok(m) => return Some(Ok(m.into_owned())),
+                Err(error) => self.diagnostics.push(LineDiagnostic {
+                    line_number,
+                    line: self.entries.line().to_vec(),
+                    error,
+                }),
+            }
+        }
+    }
+}
+
+/// Get a list of all mount points from `root` and beneath using a snapshot of */proc/mounts*,
+/// skipping unparseable lines
+pub fn get_submounts_lenient<T>(root: T) -> Result<(Vec<MountEntry>, Vec<LineDiagnostic>), ParseError>
+        where T: AsRef<Path> {
+    let mut iter = MountIter::new_from_proc_snapshot()?.lenient();
+    let mounts = get_submounts_from(root, &mut iter)?;
+    Ok((mounts, iter.into_diagnostics()))
+}
+
+/// Get the mount point for the `target` using a snapshot of */proc/mounts*, skipping
+/// unparseable lines
+pub fn get_mount_lenient<T>(target: T) -> Result<(Option<MountEntry>, Vec<LineDiagnostic>), ParseError>
+        where T: AsRef<Path> {
+    let mut iter = MountIter::new_from_proc_snapshot()?.lenient();
+    let mount = get_mount_from(target, &mut iter)?;
+    Ok((mount, iter.into_diagnostics()))
+}
+
+
+#[cfg(test)]
+mod test {
+    use error::{LineError, LineErrorKind};
+    use parse::{MountIter, get_mount_from, get_submounts_from};
+    use std::io::Cursor;
+    use std::path::Path;
+    use super::LineDiagnostic;
+
+    #[test]
+    fn test_lenient() {
+        let buf = Cursor::new(b"\
+            rootfs / rootfs rw 0 0\n\
+            /dev/mapper/swap none swap sw 0 0\n\
+            tmpfs /run tmpfs rw 0\n\
+            tmpfs /tmp tmpfs rw 0 0\n\
+        ".as_ref());
+        assert!(get_submounts_from("/", MountIter::new(buf.clone())).is_err());
+
+        let mut mounts = MountIter::new(buf.clone()).lenient();
+        let subs = get_submounts_from("/", &mut mounts).unwrap();
+        assert_eq!(subs.iter().map(|m| m.file.as_path()).collect::<Vec<_>>(),
+                   vec!(Path::new("/"), Path::new("/tmp")));
+        assert_eq!(mounts.diagnostics(), &[
+            LineDiagnostic {
+                line_number: 2,
+                line: b"/dev/mapper/swap none swap sw 0 0".to_vec(),
+                error: LineError::new(LineErrorKind::InvalidFilePath("none".into()), 17..21),
+            },
+            LineDiagnostic {
+                line_number: 3,
+                line: b"tmpfs /run tmpfs rw 0".to_vec(),
+                error: LineError::new(LineErrorKind::MissingPassno, 21..21),
+            },
+        ]);
+
+        let mut mounts = MountIter::new(buf).lenient();
+        let mount = get_mount_from("/tmp/foo", &mut mounts).unwrap().unwrap();
+        assert_eq!(mount.file, Path::new("/tmp"));
+        assert_eq!(mounts.into_diagnostics().len(), 2);
+    }
+}
@@ -12,8 +12,47 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+pub use backing::*;
+pub use diff::*;
 pub use error::*;
+pub use filesystems::*;
+pub use flags::*;
+pub use fsclass::*;
+pub use fstab::*;
+pub use fstabd::*;
+pub use genfstab::*;
+pub use lenient::*;
+pub use lint::*;
+pub use mntops::*;
+pub use mountinfo::*;
+pub use overlay::*;
 pub use parse::*;
+pub use plan::*;
+pub use resolve::*;
+pub use snapshot::*;
+pub use source::*;
+pub use sysroot::*;
+pub use utab::*;
 
+mod backing;
+mod bind;
+mod diff;
 mod error;
+mod filesystems;
+mod flags;
+mod fsclass;
+mod fstab;
+mod fstabd;
+mod genfstab;
+mod lenient;
+mod lint;
+mod mntops;
+mod mountinfo;
+mod overlay;
 mod parse;
+mod plan;
+mod resolve;
+mod snapshot;
+mod source;
+mod sysroot;
+mod utab;
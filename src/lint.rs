@@ -0,0 +1,325 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+extern crate libc;
+
+use error::*;
+use filesystems::Filesystems;
+use fsclass::FsClass;
+use parse::{MntOps, MountEntry, MountRefIter, PassField};
+use self::libc::c_int;
+use std::fmt;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+/// Mount options cancelling each other
+const CONFLICTING_OPTIONS: &[(&str, &str)] = &[
+    ("rw", "ro"),
+    ("exec", "noexec"),
+    ("suid", "nosuid"),
+    ("dev", "nodev"),
+    ("atime", "noatime"),
+    ("diratime", "nodiratime"),
+    ("relatime", "norelatime"),
+    ("auto", "noauto"),
+    ("user", "nouser"),
+    ("async", "sync"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        out.write_str(match *self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LintKind {
+    BadLine(LineError),
+    RelativePath(PathBuf),
+    DuplicateTarget { target: PathBuf, first_line: usize },
+    ChildBeforeParent { target: PathBuf, parent: PathBuf, parent_line: usize },
+    ConflictingOptions(String, String),
+    PseudoPassno { vfstype: String, passno: c_int },
+    RootPassno(PassField),
+    UnknownFsType(String),
+}
+
+impl LintKind {
+    pub fn severity(&self) -> Severity {
+        match *self {
+            LintKind::BadLine(_) |
+            LintKind::RelativePath(_) |
+            LintKind::DuplicateTarget { .. } |
+            LintKind::ChildBeforeParent { .. } => Severity::Error,
+            LintKind::ConflictingOptions(..) |
+            LintKind::PseudoPassno { .. } |
+            LintKind::RootPassno(_) |
+            LintKind::UnknownFsType(_) => Severity::Warning,
+        }
+    }
+
+    /// Stable identifier of the check
+    pub fn code(&self) -> &'static str {
+        match *self {
+            LintKind::BadLine(_) => "bad-line",
+            LintKind::RelativePath(_) => "relative-path",
+            LintKind::DuplicateTarget { .. } => "duplicate-target",
+            LintKind::ChildBeforeParent { .. } => "child-before-parent",
+            LintKind::ConflictingOptions(..) => "conflicting-options",
+            LintKind::PseudoPassno { .. } => "pseudo-passno",
+            LintKind::RootPassno(_) => "root-passno",
+            LintKind::UnknownFsType(_) => "unknown-fstype",
+        }
+    }
+}
+
+impl fmt::Display for LintKind {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LintKind::BadLine(ref e) => write!(out, "{}", e),
+            LintKind::RelativePath(ref p) => write!(out, "Relative path: {}", p.display()),
+            LintKind::DuplicateTarget { ref target, first_line } =>
+                write!(out, "Duplicate target {} (first defined at line {})", target.display(), first_line),
+            LintKind::ChildBeforeParent { ref target, ref parent, parent_line } =>
+                write!(out, "Target {} listed before its parent mount point {} (line {})",
+                       target.display(), parent.display(), parent_line),
+            LintKind::ConflictingOptions(ref a, ref b) => write!(out, "Conflicting options: {} and {}", a, b),
+            LintKind::PseudoPassno { ref vfstype, passno } =>
+                write!(out, "Pseudo filesystem {} with a nonzero passno: {}", vfstype, passno),
+            LintKind::RootPassno(passno) =>
+                write!(out, "Root filesystem should use passno 1 instead of {}", passno.unwrap_or(0)),
+            LintKind::UnknownFsType(ref t) => write!(out, "Unknown filesystem type: {}", t),
+        }
+    }
+}
+
+/// Problem found in an fstab line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lint {
+    /// Line number, starting from 1
+    pub line_number: usize,
+    /// Raw content of the line, without the trailing newline
+    pub line: Vec<u8>,
+    pub kind: LintKind,
+}
+
+impl Lint {
+    /// Print the lint with its location, and a caret under the bad token for unparseable lines
+    pub fn render<T>(&self, source_file: T) -> String where T: AsRef<Path> {
+        match self.kind {
+            LintKind::BadLine(ref e) =>
+                render_line_error(e, Some(source_file.as_ref()), Some(self.line_number), &self.line),
+            ref kind => format!("{}:{}: {}: {}", source_file.as_ref().display(), self.line_number,
+                                kind.severity(), kind),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LintOptions {
+    /// Filesystem types supported by the system, e.g. from */proc/filesystems*
+    pub filesystems: Option<Filesystems>,
+}
+
+/// Check if the filesystem type has no backing device to check
+fn is_pseudo(vfstype: &str) -> bool {
+    match FsClass::from_known(vfstype) {
+        Some(FsClass::Pseudo) | Some(FsClass::Memory) | Some(FsClass::Autofs) => true,
+        _ => vfstype == "swap",
+    }
+}
+
+/// Check the parsed fstab `entries` along with their line numbers
+pub fn lint_entries(entries: &[(usize, MountEntry)], options: &LintOptions) -> Vec<(usize, LintKind)> {
+    let mut lints = vec!();
+    let root = Path::new("/");
+    for (i, &(nb, ref entry)) in entries.iter().enumerate() {
+        let mut lint = |kind| lints.push((nb, kind));
+
+        let is_bind = entry.mntops.iter().any(|o| match *o {
+            MntOps::Extra(ref e) => e == "bind" || e == "rbind",
+            _ => false,
+        });
+        if is_bind && Path::new(&entry.spec).is_relative() {
+            lint(LintKind::RelativePath(PathBuf::from(&entry.spec)));
+        }
+
+        // Swap entries don't have a target
+        let is_swap = entry.vfstype == "swap";
+        if let Some(&(first_line, _)) = entries[..i].iter().find(|e| !is_swap && e.1.file == entry.file) {
+            lint(LintKind::DuplicateTarget { target: entry.file.clone(), first_line });
+        }
+        let parent = entries[i + 1..].iter()
+            .filter(|e| !is_swap && e.1.file != entry.file && entry.file.starts_with(&e.1.file))
+            .max_by_key(|e| e.1.file.components().count());
+        if let Some(&(parent_line, ref parent)) = parent {
+            lint(LintKind::ChildBeforeParent {
+                target: entry.file.clone(),
+                parent: parent.file.clone(),
+                parent_line,
+            });
+        }
+
+        let mntops: Vec<String> = entry.mntops.iter().map(|o| o.to_string()).collect();
+        for &(a, b) in CONFLICTING_OPTIONS {
+            let pos_a = mntops.iter().position(|o| o == a);
+            let pos_b = mntops.iter().position(|o| o == b);
+            if let (Some(pos_a), Some(pos_b)) = (pos_a, pos_b) {
+                let (first, second) = if pos_a < pos_b { (a, b) } else { (b, a) };
+                lint(LintKind::ConflictingOptions(first.into(), second.into()));
+            }
+        }
+
+        match entry.passno {
+            Some(passno) if is_pseudo(&entry.vfstype) => lint(LintKind::PseudoPassno {
+                vfstype: entry.vfstype.clone(),
+                passno,
+            }),
+            passno if entry.file == root && passno != Some(1) && !is_pseudo(&entry.vfstype) =>
+                lint(LintKind::RootPassno(passno)),
+            _ => {},
+        }
+
+        if let Some(ref filesystems) = options.filesystems {
+            for vfstype in entry.vfstype.split(',').filter(|t| !is_swap && !filesystems.is_supported(t)) {
+                lint(LintKind::UnknownFsType(vfstype.into()));
+            }
+        }
+    }
+    lints
+}
+
+/// Check an fstab file, unparseable lines being reported as lints
+pub fn lint<T>(fstab: T, options: &LintOptions) -> Result<Vec<Lint>, ParseError> where T: BufRead {
+    let mut lines = MountRefIter::new(fstab).skip_comments();
+    let mut raw_lines = vec!();
+    let mut entries = vec!();
+    let mut lints = vec!();
+    while let Some(nb) = lines.next_line() {
+        let nb = nb.map_err(|e| lines.locate(From::from(e)))?;
+        let line = lines.line();
+        match lines.parse_line() {
+            Ok(m) => entries.push((nb, m.into_owned())),
+            Err(e) => {
+                let kind = match *e.kind() {
+                    LineErrorKind::InvalidFilePath(ref f) => LintKind::RelativePath(PathBuf::from(f)),
+                    _ => LintKind::BadLine(e.clone()),
+                };
+                lints.push(Lint { line_number: nb, line: line.to_vec(), kind });
+            }
+        }
+        raw_lines.push((nb, line.to_vec()));
+    }
+    for (nb, kind) in lint_entries(&entries, options) {
+        let line = raw_lines.iter().find(|l| l.0 == nb).map(|l| l.1.clone()).unwrap_or_default();
+        lints.push(Lint { line_number: nb, line, kind });
+    }
+    lints.sort_by_key(|l| l.line_number);
+    Ok(lints)
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Serialize lints as a JSON array
+pub fn lints_to_json<T>(source_file: T, lints: &[Lint]) -> String where T: AsRef<Path> {
+    let file = json_string(&source_file.as_ref().to_string_lossy());
+    let items: Vec<String> = lints.iter().map(|l| {
+        format!("{{\"file\":{},\"line\":{},\"severity\":{},\"code\":{},\"message\":{}}}",
+                file, l.line_number, json_string(&l.kind.severity().to_string()),
+                json_string(l.kind.code()), json_string(&l.kind.to_string()))
+    }).collect();
+    format!("[{}]", items.join(","))
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::path::PathBuf;
+    use filesystems::Filesystems;
+    use super::{LintKind, LintOptions, Severity, lint, lints_to_json};
+
+    #[test]
+    fn test_lint() {
+        let fstab = Cursor::new(b"\
+            # /etc/fstab\n\
+            \n\
+            /dev/sda2 /home ext4 defaults 0 2\n\
+            /dev/sda1 / ext4 rw,noexec,exec 0 2\n\
+            /dev/sda3 /home xfs defaults 0 2\n\
+            proc /proc proc defaults 0 1\n\
+            /dev/sda4 none swap sw 0 0\n\
+            /dev/sda6 none swap sw 0 x\n\
+            data /srv/data none bind 0 0\n\
+            /dev/sda5 /mnt foofs defaults 0 0\n\
+            tmpfs tmp tmpfs defaults 0 0\n\
+            tmpfs /tmp tmpfs defaults 0\n\
+        ".as_ref());
+        let filesystems = Filesystems::new(b"\text4\n\txfs\nnodev\tproc\nnodev\ttmpfs\n".as_ref()).unwrap();
+        let options = LintOptions {
+            filesystems: Some(filesystems),
+        };
+        let lints = lint(fstab, &options).unwrap();
+        let kinds: Vec<_> = lints.iter().map(|l| (l.line_number, l.kind.code())).collect();
+        assert_eq!(kinds, vec!(
+            (3, "child-before-parent"),
+            (4, "conflicting-options"),
+            (4, "root-passno"),
+            (5, "duplicate-target"),
+            (6, "pseudo-passno"),
+            (8, "bad-line"),
+            (9, "relative-path"),
+            (10, "unknown-fstype"),
+            (11, "relative-path"),
+            (12, "bad-line"),
+        ));
+        assert_eq!(lints[0].kind, LintKind::ChildBeforeParent {
+            target: PathBuf::from("/home"),
+            parent: PathBuf::from("/"),
+            parent_line: 4,
+        });
+        assert_eq!(lints[1].kind, LintKind::ConflictingOptions("noexec".into(), "exec".into()));
+        assert_eq!(lints[2].kind.severity(), Severity::Warning);
+        assert_eq!(lints[3].render("/etc/fstab"),
+                   "/etc/fstab:5: error: Duplicate target /home (first defined at line 3)");
+        assert!(lints[9].render("/etc/fstab").contains("^"));
+        assert!(lints_to_json("/etc/fstab", &lints[3..4]).starts_with(
+            "[{\"file\":\"/etc/fstab\",\"line\":5,\"severity\":\"error\",\"code\":\"duplicate-target\""));
+    }
+}
@@ -0,0 +1,312 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use parse::MntOps;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::FromIterator;
+
+/// Options set by `defaults`
+const DEFAULTS: &[&str] = &["rw", "suid", "dev", "exec", "auto", "nouser", "async"];
+
+/// Extra options overriding each other, the first one being the key
+const EXTRA_TOGGLES: &[(&str, &str)] = &[
+    ("auto", "noauto"),
+    ("user", "nouser"),
+    ("async", "sync"),
+    ("mand", "nomand"),
+    ("iversion", "noiversion"),
+    ("lazytime", "nolazytime"),
+];
+
+/// Prefix of the userspace options which may be repeated, e.g. `x-systemd.requires=`
+const REPEATABLE_PREFIX: &str = "x-";
+
+/// Access time update mode, each mode replacing the previous one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AtimeMode {
+    /// `relatime`
+    Relative,
+    /// `noatime`
+    Never,
+    /// `strictatime`
+    Strict,
+}
+
+/// Effective mount options, where the last option wins
+///
+/// Unspecified `rw`, `suid`, `dev` and `exec` options take their default value, while an
+/// unspecified access time mode is left to the kernel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MntOpsSet {
+    write: bool,
+    suid: bool,
+    dev: bool,
+    exec: bool,
+    atime: Option<AtimeMode>,
+    diratime: Option<bool>,
+    // Extra options indexed by their name, without the value, only x-* ones being repeatable
+    extra: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for MntOpsSet {
+    fn default() -> MntOpsSet {
+        MntOpsSet {
+            write: true,
+            suid: true,
+            dev: true,
+            exec: true,
+            atime: None,
+            diratime: None,
+            extra: BTreeMap::new(),
+        }
+    }
+}
+
+fn extra_key(extra: &str) -> &str {
+    for &(yes, no) in EXTRA_TOGGLES {
+        if extra == yes || extra == no {
+            return yes;
+        }
+    }
+    extra.split('=').next().unwrap_or(extra)
+}
+
+impl MntOpsSet {
+    pub fn new<'a, T>(mntops: T) -> MntOpsSet where T: IntoIterator<Item = &'a MntOps> {
+        let mut set = MntOpsSet::default();
+        for ops in mntops {
+            set.insert(ops);
+        }
+        set
+    }
+
+    /// Reset the access time mode to the kernel default if it is `mode`
+    fn clear_atime(&mut self, mode: AtimeMode) {
+        if self.atime == Some(mode) {
+            self.atime = None;
+        }
+    }
+
+    /// Apply an option, overriding the previous conflicting ones
+    pub fn insert(&mut self, ops: &MntOps) {
+        match *ops {
+            MntOps::Atime(false) => self.atime = Some(AtimeMode::Never),
+            MntOps::Atime(true) => self.clear_atime(AtimeMode::Never),
+            MntOps::RelAtime(true) => self.atime = Some(AtimeMode::Relative),
+            MntOps::RelAtime(false) => self.clear_atime(AtimeMode::Relative),
+            MntOps::DirAtime(b) => self.diratime = Some(b),
+            MntOps::Dev(b) => self.dev = b,
+            MntOps::Exec(b) => self.exec = b,
+            MntOps::Suid(b) => self.suid = b,
+            MntOps::Write(b) => self.write = b,
+            MntOps::Extra(ref extra) if extra == "defaults" => {
+                for ops in DEFAULTS {
+                    self.insert(&ops.parse().unwrap());
+                }
+            }
+            MntOps::Extra(ref extra) if extra == "strictatime" => self.atime = Some(AtimeMode::Strict),
+            MntOps::Extra(ref extra) if extra == "nostrictatime" => self.clear_atime(AtimeMode::Strict),
+            MntOps::Extra(ref extra) => {
+                let values = self.extra.entry(extra_key(extra).to_string()).or_default();
+                if !extra.starts_with(REPEATABLE_PREFIX) {
+                    values.clear();
+                }
+                if !values.contains(extra) {
+                    values.push(extra.clone());
+                }
+            }
+        }
+    }
+
+    /// Check if the effective options include `ops`
+    pub fn contains(&self, ops: &MntOps) -> bool {
+        match *ops {
+            MntOps::Atime(b) => (self.atime != Some(AtimeMode::Never)) == b,
+            MntOps::RelAtime(b) => (self.atime == Some(AtimeMode::Relative)) == b,
+            MntOps::DirAtime(b) => self.diratime == Some(b),
+            MntOps::Dev(b) => self.dev == b,
+            MntOps::Exec(b) => self.exec == b,
+            MntOps::Suid(b) => self.suid == b,
+            MntOps::Write(b) => self.write == b,
+            MntOps::Extra(ref extra) if extra == "strictatime" || extra == "nostrictatime" =>
+                (self.atime == Some(AtimeMode::Strict)) == (extra == "strictatime"),
+            MntOps::Extra(ref extra) => self.extra.get(extra_key(extra)).map_or(false, |v| v.contains(extra)),
+        }
+    }
+
+    /// Get the value of an extra `key=value` option, the last one for a repeated x-* option
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_all(key).last()
+    }
+
+    /// Get all the values of a repeatable x-* option, e.g. `x-systemd.requires`
+    pub fn get_all<'a>(&'a self, key: &str) -> impl Iterator<Item = &'a str> {
+        self.extra.get(key).into_iter().flatten()
+            .filter_map(|e| e.split_once('=')).map(|(_, v)| v)
+    }
+
+    /// Access time mode, or `None` for the kernel default
+    pub fn atime_mode(&self) -> Option<AtimeMode> {
+        self.atime
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        !self.write
+    }
+
+    pub fn is_nosuid(&self) -> bool {
+        !self.suid
+    }
+
+    pub fn is_nodev(&self) -> bool {
+        !self.dev
+    }
+
+    pub fn is_noexec(&self) -> bool {
+        !self.exec
+    }
+
+    pub fn is_noatime(&self) -> bool {
+        self.atime == Some(AtimeMode::Never)
+    }
+
+    pub fn is_nodiratime(&self) -> bool {
+        self.diratime == Some(false)
+    }
+
+    pub fn is_relatime(&self) -> bool {
+        self.atime == Some(AtimeMode::Relative)
+    }
+
+    pub fn is_strictatime(&self) -> bool {
+        self.atime == Some(AtimeMode::Strict)
+    }
+
+    pub fn is_lazytime(&self) -> bool {
+        self.contains(&MntOps::Extra("lazytime".into()))
+    }
+
+    pub fn is_noauto(&self) -> bool {
+        self.contains(&MntOps::Extra("noauto".into()))
+    }
+
+    pub fn is_user(&self) -> bool {
+        self.contains(&MntOps::Extra("user".into()))
+    }
+
+    pub fn is_sync(&self) -> bool {
+        self.contains(&MntOps::Extra("sync".into()))
+    }
+
+    /// Extra options, sorted by name
+    pub fn extra(&self) -> impl Iterator<Item = &str> {
+        self.extra.values().flatten().map(|e| e.as_str())
+    }
+
+    /// Get the effective options, without any conflict
+    pub fn to_vec(&self) -> Vec<MntOps> {
+        let mut ret = vec!(
+            MntOps::Write(self.write),
+            MntOps::Suid(self.suid),
+            MntOps::Dev(self.dev),
+            MntOps::Exec(self.exec),
+        );
+        ret.extend(self.atime.map(|mode| match mode {
+            AtimeMode::Relative => MntOps::RelAtime(true),
+            AtimeMode::Never => MntOps::Atime(false),
+            AtimeMode::Strict => MntOps::Extra("strictatime".into()),
+        }));
+        ret.extend(self.diratime.map(MntOps::DirAtime));
+        ret.extend(self.extra().map(|e| MntOps::Extra(e.into())));
+        ret
+    }
+}
+
+impl<'a> FromIterator<&'a MntOps> for MntOpsSet {
+    fn from_iter<T>(iter: T) -> MntOpsSet where T: IntoIterator<Item = &'a MntOps> {
+        MntOpsSet::new(iter)
+    }
+}
+
+impl fmt::Display for MntOpsSet {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        let ops: Vec<String> = self.to_vec().iter().map(|o| o.to_string()).collect();
+        write!(out, "{}", ops.join(","))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use parse::MntOps;
+    use super::{AtimeMode, MntOpsSet};
+
+    fn set(mntops: &str) -> MntOpsSet {
+        mntops.split(',').map(|o| o.parse::<MntOps>().unwrap()).collect::<Vec<_>>().iter().collect()
+    }
+
+    #[test]
+    fn test_last_wins() {
+        let ops = set("ro,rw,noexec,exec,nosuid,mode=755,mode=700,noauto,auto");
+        assert!(!ops.is_read_only());
+        assert!(!ops.is_noexec());
+        assert!(ops.is_nosuid());
+        assert!(ops.contains(&MntOps::Write(true)));
+        assert!(!ops.contains(&MntOps::Write(false)));
+        assert!(ops.contains(&MntOps::Extra("mode=700".into())));
+        assert!(!ops.contains(&MntOps::Extra("mode=755".into())));
+        assert_eq!(ops.get("mode"), Some("700"));
+        assert!(!ops.is_noauto());
+        assert!(set("rw,ro").is_read_only());
+        assert_eq!(set("mode=755,size=1k"), set("size=1k,mode=700,mode=755"));
+    }
+
+    #[test]
+    fn test_defaults() {
+        let ops = set("noexec,defaults,ro");
+        assert!(ops.is_read_only());
+        assert!(!ops.is_noexec());
+        assert!(!ops.is_user());
+        assert!(!ops.is_sync());
+        assert_eq!(ops.to_string(), "ro,suid,dev,exec,async,auto,nouser");
+        assert_eq!(set("nodev").to_string(), "rw,suid,nodev,exec");
+        assert!(set("defaults,user").is_user());
+    }
+
+    #[test]
+    fn test_atime_mode() {
+        let ops = set("relatime,noatime");
+        assert_eq!(ops.atime_mode(), Some(AtimeMode::Never));
+        assert!(!ops.is_relatime());
+        assert_eq!(ops.to_string(), "rw,suid,dev,exec,noatime");
+        assert_eq!(set("noatime,strictatime").atime_mode(), Some(AtimeMode::Strict));
+        assert_eq!(set("strictatime,relatime").to_string(), "rw,suid,dev,exec,relatime");
+        assert_eq!(set("noatime,atime").atime_mode(), None);
+        assert!(set("noatime,atime").contains(&MntOps::Atime(true)));
+        assert_eq!(set("relatime,atime").atime_mode(), Some(AtimeMode::Relative));
+        let ops = set("lazytime,noatime,nodiratime");
+        assert!(ops.is_lazytime() && ops.is_noatime() && ops.is_nodiratime());
+        assert!(!set("lazytime,nolazytime").is_lazytime());
+    }
+
+    #[test]
+    fn test_repeatable() {
+        let ops = set("x-systemd.requires=a,x-systemd.requires=b,x-systemd.requires=a,mode=1,mode=2");
+        assert_eq!(ops.get_all("x-systemd.requires").collect::<Vec<_>>(), vec!("a", "b"));
+        assert!(ops.contains(&MntOps::Extra("x-systemd.requires=a".into())));
+        assert_eq!(ops.get_all("mode").collect::<Vec<_>>(), vec!("2"));
+        assert_eq!(ops.to_string(), "rw,suid,dev,exec,mode=2,x-systemd.requires=a,x-systemd.requires=b");
+    }
+}
@@ -0,0 +1,353 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+extern crate libc;
+
+use error::*;
+use parse::{DumpField, MntOps, MountEntry, MountRefIter, fields, fields_end};
+use snapshot::read_snapshot;
+use std::ffi::OsString;
+use std::fmt;
+use std::io::{BufRead, Cursor};
+use std::ops::Range;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::str::{self, FromStr};
+
+const PROC_MOUNTINFO: &str = "/proc/self/mountinfo";
+
+/// Device number, as `major:minor`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DeviceId {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl DeviceId {
+    pub fn new(major: u32, minor: u32) -> DeviceId {
+        DeviceId {
+            major,
+            minor,
+        }
+    }
+
+    pub fn to_dev_t(self) -> libc::dev_t {
+        libc::makedev(self.major, self.minor)
+    }
+}
+
+impl From<libc::dev_t> for DeviceId {
+    fn from(dev: libc::dev_t) -> DeviceId {
+        DeviceId::new(libc::major(dev), libc::minor(dev))
+    }
+}
+
+impl FromStr for DeviceId {
+    type Err = ();
+
+    fn from_str(dev: &str) -> Result<DeviceId, ()> {
+        let (major, minor) = dev.split_once(':').ok_or(())?;
+        Ok(DeviceId::new(major.parse().map_err(|_| ())?, minor.parse().map_err(|_| ())?))
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "{}:{}", self.major, self.minor)
+    }
+}
+
+/// Decode the octal escapes (e.g. `\040` for a space) used by the kernel in mount tables
+pub fn unescape_octal(field: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(field.len());
+    let mut i = 0;
+    while i < field.len() {
+        let digits = field.get(i + 1..i + 4).filter(|d| d.iter().all(|c| (b'0'..=b'7').contains(c)));
+        match digits {
+            Some(d) if field[i] == b'\\' => {
+                ret.push(d.iter().fold(0u8, |acc, c| acc.wrapping_mul(8) + (c - b'0')));
+                i += 4;
+            }
+            _ => {
+                ret.push(field[i]);
+                i += 1;
+            }
+        }
+    }
+    ret
+}
+
+/// Encode the blanks and backslashes of a mount table field with octal escapes
+pub fn escape_octal(field: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(field.len());
+    for &c in field {
+        match c {
+            b' ' | b'\t' | b'\n' | b'\\' => ret.extend(format!("\\{:03o}", c).bytes()),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+/// Entry of */proc/self/mountinfo*
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MountInfoEntry {
+    pub mount_id: u64,
+    pub parent_id: u64,
+    pub dev: DeviceId,
+    /// Path of the directory in the filesystem which forms the root of this mount
+    pub root: PathBuf,
+    pub mount_point: PathBuf,
+    /// Per-mount options
+    pub mntops: Vec<MntOps>,
+    /// Tagged fields, e.g. `shared:1` or `master:2`
+    pub optional_fields: Vec<String>,
+    pub vfstype: String,
+    pub source: OsString,
+    /// Per-superblock options
+    pub super_options: Vec<MntOps>,
+}
+
+impl MountInfoEntry {
+    pub fn parse<T>(line: &T) -> Result<MountInfoEntry, LineError> where T: AsRef<[u8]> + ?Sized {
+        let line = line.as_ref();
+        let end = fields_end(line);
+        let mut tokens = fields(line);
+        let mut next = |name| tokens.next().ok_or_else(||
+            LineError::new(LineErrorKind::MissingField(name), end..end));
+        let text = |name, span: Range<usize>| str::from_utf8(&line[span.clone()]).map_err(|_|
+            LineError::new(LineErrorKind::InvalidField(name, String::from_utf8_lossy(&line[span.clone()]).into()), span));
+        let number = |name, span: Range<usize>| {
+            let value = text(name, span.clone())?;
+            value.parse().map_err(|_| LineError::new(LineErrorKind::InvalidField(name, value.into()), span))
+        };
+        let path = |span: Range<usize>| PathBuf::from(OsString::from_vec(unescape_octal(&line[span])));
+        let options = |value: &str| -> Vec<MntOps> { value.split_terminator(',').map(|o| o.parse().unwrap()).collect() };
+
+        let mount_id = number("mount ID", next("mount ID")?)?;
+        let parent_id = number("parent ID", next("parent ID")?)?;
+        let dev = {
+            let span = next("major:minor")?;
+            let value = text("major:minor", span.clone())?;
+            value.parse().map_err(|_| LineError::new(LineErrorKind::InvalidField("major:minor", value.into()), span))?
+        };
+        let root = path(next("root")?);
+        let mount_point = path(next("mount point")?);
+        let mntops = options(text("mount options", next("mount options")?)?);
+        let mut optional_fields = vec!();
+        loop {
+            let span = next("separator")?;
+            match text("optional fields", span)? {
+                "-" => break,
+                field => optional_fields.push(field.to_string()),
+            }
+        }
+        let vfstype = text("filesystem type", next("filesystem type")?)?.to_string();
+        let source = OsString::from_vec(unescape_octal(&line[next("mount source")?]));
+        let super_options = options(text("super options", next("super options")?)?);
+        Ok(MountInfoEntry {
+            mount_id,
+            parent_id,
+            dev,
+            root,
+            mount_point,
+            mntops,
+            optional_fields,
+            vfstype,
+            source,
+            super_options,
+        })
+    }
+
+    /// Convert to a */proc/mounts* entry, merging the per-mount and the per-superblock options
+    pub fn to_mount_entry(&self) -> MountEntry {
+        let read_only = self.super_options.contains(&MntOps::Write(false));
+        let mut mntops: Vec<MntOps> = self.mntops.iter().map(|o| match *o {
+            MntOps::Write(true) if read_only => MntOps::Write(false),
+            ref o => o.clone(),
+        }).collect();
+        mntops.extend(self.super_options.iter().filter(|o| match **o {
+            MntOps::Write(_) => false,
+            ref o => !self.mntops.contains(o),
+        }).cloned());
+        MountEntry {
+            spec: self.source.clone(),
+            file: self.mount_point.clone(),
+            vfstype: self.vfstype.clone(),
+            mntops,
+            freq: DumpField::Ignore,
+            passno: None,
+        }
+    }
+}
+
+impl FromStr for MountInfoEntry {
+    type Err = LineError;
+
+    fn from_str(line: &str) -> Result<MountInfoEntry, LineError> {
+        MountInfoEntry::parse(line)
+    }
+}
+
+pub struct MountInfoIter<T> {
+    entries: MountRefIter<T>,
+}
+
+impl<T> MountInfoIter<T> where T: BufRead {
+    pub fn new(mountinfo: T) -> MountInfoIter<T> {
+        MountInfoIter {
+            entries: MountRefIter::new(mountinfo),
+        }
+    }
+
+    /// Set the mountinfo path reported by the errors
+    pub fn with_source_file<U>(self, path: U) -> MountInfoIter<T> where U: Into<PathBuf> {
+        MountInfoIter {
+            entries: self.entries.with_source_file(path),
+        }
+    }
+}
+
+impl MountInfoIter<Cursor<Vec<u8>>> {
+    /// Iterate over a consistent snapshot of the mountinfo file `path`
+    pub fn new_from_snapshot<T>(path: T) -> Result<MountInfoIter<Cursor<Vec<u8>>>, ParseError>
+            where T: AsRef<Path> {
+        let snapshot = read_snapshot(&path).map_err(|e| ParseError::from(e).with_source_file(path.as_ref()))?;
+        Ok(MountInfoIter::new(Cursor::new(snapshot)).with_source_file(path.as_ref()))
+    }
+
+    /// Iterate over a consistent snapshot of */proc/self/mountinfo*
+    pub fn new_from_proc() -> Result<MountInfoIter<Cursor<Vec<u8>>>, ParseError> {
+        MountInfoIter::new_from_snapshot(PROC_MOUNTINFO)
+    }
+}
+
+impl<T> Iterator for MountInfoIter<T> where T: BufRead {
+    type Item = Result<MountInfoEntry, ParseError>;
+
+    fn next(&mut self) -> Option<<Self as Iterator>::Item> {
+        let nb = match self.entries.next_line()? {
+            Ok(nb) => nb,
+            Err(e) => return Some(Err(self.entries.locate(From::from(e)))),
+        };
+        match MountInfoEntry::parse(self.entries.line()) {
+            Ok(m) => Some(Ok(m)),
+            Err(e) => Some(Err(self.entries.locate(ParseError::from_line(e, nb, self.entries.line())))),
+        }
+    }
+}
+
+/// Snapshot of the mount table with the mount IDs and the filesystem roots
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MountTable {
+    entries: Vec<MountInfoEntry>,
+}
+
+impl MountTable {
+    pub fn new(entries: Vec<MountInfoEntry>) -> MountTable {
+        MountTable {
+            entries,
+        }
+    }
+
+    /// Load a consistent snapshot of */proc/self/mountinfo*
+    pub fn new_from_proc() -> Result<MountTable, ParseError> {
+        MountInfoIter::new_from_proc()?.collect::<Result<_, _>>().map(MountTable::new)
+    }
+
+    /// Entries in mount order
+    pub fn entries(&self) -> &[MountInfoEntry] {
+        &self.entries
+    }
+
+    pub fn get_by_id(&self, mount_id: u64) -> Option<&MountInfoEntry> {
+        self.entries.iter().find(|m| m.mount_id == mount_id)
+    }
+
+    /// Get all the mounts of the device `dev`
+    pub fn find_by_dev(&self, dev: DeviceId) -> impl Iterator<Item = &MountInfoEntry> {
+        self.entries.iter().filter(move |m| m.dev == dev)
+    }
+
+    /// Get the mount point for the `target` path by string prefix matching
+    ///
+    /// This offline mode doesn't access `target`, hence doesn't handle symlinks, relative paths,
+    /// `..` components nor bind mounts.  The last matching entry is the visible one.
+    pub fn lookup_prefix<T>(&self, target: T) -> Option<&MountInfoEntry> where T: AsRef<Path> {
+        self.entries.iter().rev().find(|m| target.as_ref().starts_with(&m.mount_point))
+    }
+
+    /// Build a table from a mountinfo fixture
+    #[cfg(test)]
+    pub(crate) fn from_fixture(mountinfo: &[u8]) -> MountTable {
+        MountTable::new(MountInfoIter::new(mountinfo).map(|m| m.unwrap()).collect())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use parse::{MntOps, MountEntry};
+    use std::path::Path;
+    use std::str::FromStr;
+    use super::{DeviceId, MountInfoEntry, MountTable, escape_octal, unescape_octal};
+
+    const MOUNTINFO: &[u8] = b"\
+        22 1 254:0 / / rw,relatime shared:1 - ext4 /dev/mapper/root rw,errors=remount-ro\n\
+        23 22 0:21 / /proc rw,nosuid,nodev,noexec,relatime shared:12 - proc proc rw\n\
+        40 22 254:0 /srv/data /jail/my\\040data ro,relatime - ext4 /dev/mapper/root rw,errors=remount-ro\n\
+    ";
+
+    #[test]
+    fn test_mountinfo_line() {
+        let entry = MountInfoEntry::from_str("36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root ro,errors=continue").unwrap();
+        assert_eq!(entry.mount_id, 36);
+        assert_eq!(entry.parent_id, 35);
+        assert_eq!(entry.dev, DeviceId::new(98, 0));
+        assert_eq!(entry.root, Path::new("/mnt1"));
+        assert_eq!(entry.mount_point, Path::new("/mnt2"));
+        assert_eq!(entry.optional_fields, vec!("master:1".to_string()));
+        assert_eq!(entry.vfstype, "ext3");
+        assert_eq!(entry.source, "/dev/root");
+        assert_eq!(entry.to_mount_entry(), MountEntry::from_str("/dev/root /mnt2 ext3 ro,noatime,errors=continue 0 0").unwrap());
+        assert_eq!(entry.super_options, vec!(MntOps::Write(false), MntOps::Extra("errors=continue".into())));
+
+        assert!(MountInfoEntry::from_str("36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 ext3").is_err());
+        assert!(MountInfoEntry::from_str("36 35 98 /mnt1 /mnt2 rw - ext3 /dev/root rw").is_err());
+    }
+
+    #[test]
+    fn test_mountinfo_table() {
+        let table = MountTable::from_fixture(MOUNTINFO);
+        assert_eq!(table.entries().len(), 3);
+        assert_eq!(table.get_by_id(40).unwrap().mount_point, Path::new("/jail/my data"));
+        assert_eq!(table.find_by_dev(DeviceId::new(254, 0)).count(), 2);
+        assert_eq!(table.lookup_prefix("/proc/self").unwrap().mount_id, 23);
+        assert_eq!(table.lookup_prefix("/jail/my data/foo").unwrap().mount_id, 40);
+        assert_eq!(table.lookup_prefix("/jail/my").unwrap().mount_id, 22);
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape_octal(b"/a\\040b\\011c\\134\\\\12"), b"/a b\tc\\\\\\12".to_vec());
+        assert_eq!(escape_octal(b"/a b\tc\\"), b"/a\\040b\\011c\\134".to_vec());
+        assert_eq!(unescape_octal(&escape_octal(b"/a\\040 b")), b"/a\\040 b".to_vec());
+    }
+
+    #[test]
+    fn test_mountinfo_proc() {
+        let table = MountTable::new_from_proc().unwrap();
+        assert!(table.entries().iter().any(|m| m.mount_point == Path::new("/")));
+    }
+}
@@ -0,0 +1,214 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use parse::{DumpField, MntOps, MountEntry};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Overlay filesystem options, as found in the `overlay` mount entries
+///
+/// Options without a dedicated field (e.g. `rw` or `xino=on`) are kept in `other`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OverlayOptions {
+    /// Lower layers, from the top one to the bottom one
+    pub lowerdir: Vec<PathBuf>,
+    /// Data-only lower layers, after `::` or from `datadir+`
+    pub datadir: Vec<PathBuf>,
+    pub upperdir: Option<PathBuf>,
+    pub workdir: Option<PathBuf>,
+    /// `on`, `follow`, `nofollow` or `off`
+    pub redirect_dir: Option<String>,
+    pub metacopy: Option<bool>,
+    pub index: Option<bool>,
+    pub userxattr: bool,
+    pub volatile: bool,
+    pub other: Vec<MntOps>,
+}
+
+/// Split a `lowerdir` value on unescaped colons, the `\` escapes being removed
+///
+/// An empty layer marks the beginning of the data-only layers.
+fn split_layers(value: &str) -> Vec<String> {
+    let mut layers = vec!(String::new());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => layers.last_mut().unwrap().extend(chars.next()),
+            ':' => layers.push(String::new()),
+            c => layers.last_mut().unwrap().push(c),
+        }
+    }
+    layers
+}
+
+fn escape_layer(layer: &Path) -> String {
+    let mut ret = String::new();
+    for c in layer.to_string_lossy().chars() {
+        if c == ':' || c == '\\' {
+            ret.push('\\');
+        }
+        ret.push(c);
+    }
+    ret
+}
+
+fn parse_switch(value: &str) -> Option<bool> {
+    match value {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+fn switch(value: bool) -> &'static str {
+    if value { "on" } else { "off" }
+}
+
+impl OverlayOptions {
+    /// Read the overlay options, the last ones winning
+    pub fn from_mntops<'a, T>(mntops: T) -> OverlayOptions where T: IntoIterator<Item = &'a MntOps> {
+        let mut ret = OverlayOptions::default();
+        for ops in mntops {
+            let extra = match *ops {
+                MntOps::Extra(ref extra) => extra,
+                _ => {
+                    ret.other.push(ops.clone());
+                    continue;
+                }
+            };
+            let (key, value) = extra.split_once('=').unwrap_or((extra, ""));
+            match key {
+                "lowerdir" => {
+                    ret.lowerdir.clear();
+                    ret.datadir.clear();
+                    let mut data = false;
+                    for layer in split_layers(value) {
+                        match layer.as_ref() {
+                            "" => data = true,
+                            _ if data => ret.datadir.push(layer.into()),
+                            _ => ret.lowerdir.push(layer.into()),
+                        }
+                    }
+                }
+                "lowerdir+" => ret.lowerdir.push(value.into()),
+                "datadir+" => ret.datadir.push(value.into()),
+                "upperdir" => ret.upperdir = Some(value.into()),
+                "workdir" => ret.workdir = Some(value.into()),
+                "redirect_dir" => ret.redirect_dir = Some(value.into()),
+                "metacopy" if parse_switch(value).is_some() => ret.metacopy = parse_switch(value),
+                "index" if parse_switch(value).is_some() => ret.index = parse_switch(value),
+                "userxattr" => ret.userxattr = true,
+                "volatile" => ret.volatile = true,
+                _ => ret.other.push(ops.clone()),
+            }
+        }
+        ret
+    }
+
+    pub fn to_mntops(&self) -> Vec<MntOps> {
+        let mut ret = self.other.clone();
+        if !self.lowerdir.is_empty() || !self.datadir.is_empty() {
+            let mut lowerdir: Vec<String> = self.lowerdir.iter().map(|l| escape_layer(l)).collect();
+            if !self.datadir.is_empty() {
+                lowerdir.push(String::new());
+                lowerdir.extend(self.datadir.iter().map(|l| escape_layer(l)));
+            }
+            ret.push(MntOps::Extra(format!("lowerdir={}", lowerdir.join(":"))));
+        }
+        if let Some(ref upperdir) = self.upperdir {
+            ret.push(MntOps::Extra(format!("upperdir={}", upperdir.display())));
+        }
+        if let Some(ref workdir) = self.workdir {
+            ret.push(MntOps::Extra(format!("workdir={}", workdir.display())));
+        }
+        if let Some(ref redirect_dir) = self.redirect_dir {
+            ret.push(MntOps::Extra(format!("redirect_dir={}", redirect_dir)));
+        }
+        if let Some(metacopy) = self.metacopy {
+            ret.push(MntOps::Extra(format!("metacopy={}", switch(metacopy))));
+        }
+        if let Some(index) = self.index {
+            ret.push(MntOps::Extra(format!("index={}", switch(index))));
+        }
+        if self.userxattr {
+            ret.push(MntOps::Extra("userxattr".into()));
+        }
+        if self.volatile {
+            ret.push(MntOps::Extra("volatile".into()));
+        }
+        ret
+    }
+
+    /// Build an `overlay` mount entry for the mount point `file`
+    pub fn to_mount_entry<T>(&self, file: T) -> MountEntry where T: Into<PathBuf> {
+        MountEntry {
+            spec: OsString::from("overlay"),
+            file: file.into(),
+            vfstype: "overlay".into(),
+            mntops: self.to_mntops(),
+            freq: DumpField::Ignore,
+            passno: None,
+        }
+    }
+}
+
+impl MountEntry {
+    /// Get the overlay options, if this is an `overlay` mount
+    pub fn overlay_options(&self) -> Option<OverlayOptions> {
+        match self.vfstype.as_ref() {
+            "overlay" => Some(OverlayOptions::from_mntops(&self.mntops)),
+            _ => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use parse::{MntOps, MountEntry};
+    use std::path::PathBuf;
+    use super::OverlayOptions;
+
+    #[test]
+    fn test_overlay_options() {
+        let entry: MountEntry = "overlay /merged overlay rw,lowerdir=/l1:/l\\:2::/d1,upperdir=/up,\
+            workdir=/work,index=off,metacopy=maybe,userxattr,xino=on 0 0".parse().unwrap();
+        let ops = entry.overlay_options().unwrap();
+        assert_eq!(ops.lowerdir, vec!(PathBuf::from("/l1"), PathBuf::from("/l:2")));
+        assert_eq!(ops.datadir, vec!(PathBuf::from("/d1")));
+        assert_eq!(ops.upperdir, Some(PathBuf::from("/up")));
+        assert_eq!(ops.workdir, Some(PathBuf::from("/work")));
+        assert_eq!(ops.index, Some(false));
+        assert_eq!(ops.metacopy, None);
+        assert!(ops.userxattr);
+        assert!(!ops.volatile);
+        assert_eq!(ops.other, vec!(MntOps::Write(true), MntOps::Extra("metacopy=maybe".into()),
+            MntOps::Extra("xino=on".into())));
+
+        let round_trip = ops.to_mount_entry("/merged");
+        assert_eq!(round_trip.overlay_options(), Some(ops));
+        assert_eq!(round_trip.spec, "overlay");
+        assert!("/dev/sda1 / ext4 rw 0 1".parse::<MountEntry>().unwrap().overlay_options().is_none());
+    }
+
+    #[test]
+    fn test_lowerdir_append() {
+        let ops: Vec<MntOps> = "lowerdir+=/l1,lowerdir+=/a:b,datadir+=/d1".split(',')
+            .map(|o| o.parse().unwrap()).collect();
+        let ops = OverlayOptions::from_mntops(&ops);
+        assert_eq!(ops.lowerdir, vec!(PathBuf::from("/l1"), PathBuf::from("/a:b")));
+        assert_eq!(ops.datadir, vec!(PathBuf::from("/d1")));
+        assert_eq!(ops.to_mntops(), vec!(MntOps::Extra("lowerdir=/l1:/a\\:b::/d1".into())));
+    }
+}
@@ -15,17 +15,24 @@
 extern crate libc;
 
 use error::*;
+use mntops::MntOpsSet;
+use mountinfo::{escape_octal, unescape_octal};
+use resolve::get_mount;
 use self::libc::c_int;
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::convert::{AsRef, From};
+use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::fs::File;
-use std::io::{BufReader, BufRead, Lines};
-use std::iter::Enumerate;
+use std::io::{self, BufReader, BufRead};
+use std::iter;
+use std::ops::Range;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
+use std::str::{self, FromStr};
 
-const PROC_MOUNTS: &'static str = "/proc/mounts";
+pub(crate) const PROC_MOUNTS: &str = "/proc/mounts";
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum DumpField {
@@ -47,27 +54,96 @@ pub enum MntOps {
     Extra(String),
 }
 
+impl MntOps {
+    /// Borrow as a `MntOpsRef`
+    pub fn to_ref(&self) -> MntOpsRef<'_> {
+        match *self {
+            MntOps::Atime(b) => MntOpsRef::Atime(b),
+            MntOps::DirAtime(b) => MntOpsRef::DirAtime(b),
+            MntOps::RelAtime(b) => MntOpsRef::RelAtime(b),
+            MntOps::Dev(b) => MntOpsRef::Dev(b),
+            MntOps::Exec(b) => MntOpsRef::Exec(b),
+            MntOps::Suid(b) => MntOpsRef::Suid(b),
+            MntOps::Write(b) => MntOpsRef::Write(b),
+            MntOps::Extra(ref extra) => MntOpsRef::Extra(extra),
+        }
+    }
+}
+
+impl fmt::Display for MntOps {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        self.to_ref().fmt(out)
+    }
+}
+
 impl FromStr for MntOps {
     type Err = LineError;
 
     fn from_str(token: &str) -> Result<MntOps, LineError> {
-        Ok(match token {
-            "atime" => MntOps::Atime(true),
-            "noatime" => MntOps::Atime(false),
-            "diratime" => MntOps::DirAtime(true),
-            "nodiratime" => MntOps::DirAtime(false),
-            "relatime" => MntOps::RelAtime(true),
-            "norelatime" => MntOps::RelAtime(false),
-            "dev" => MntOps::Dev(true),
-            "nodev" => MntOps::Dev(false),
-            "exec" => MntOps::Exec(true),
-            "noexec" => MntOps::Exec(false),
-            "suid" => MntOps::Suid(true),
-            "nosuid" => MntOps::Suid(false),
-            "rw" => MntOps::Write(true),
-            "ro" => MntOps::Write(false),
-            // TODO: Replace with &str
-            extra => MntOps::Extra(extra.to_string()),
+        Ok(MntOpsRef::parse(token).into_owned())
+    }
+}
+
+/// Borrowed counterpart of `MntOps`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MntOpsRef<'a> {
+    Atime(bool),
+    DirAtime(bool),
+    RelAtime(bool),
+    Dev(bool),
+    Exec(bool),
+    Suid(bool),
+    Write(bool),
+    Extra(&'a str),
+}
+
+impl<'a> MntOpsRef<'a> {
+    pub fn parse(token: &'a str) -> MntOpsRef<'a> {
+        match token {
+            "atime" => MntOpsRef::Atime(true),
+            "noatime" => MntOpsRef::Atime(false),
+            "diratime" => MntOpsRef::DirAtime(true),
+            "nodiratime" => MntOpsRef::DirAtime(false),
+            "relatime" => MntOpsRef::RelAtime(true),
+            "norelatime" => MntOpsRef::RelAtime(false),
+            "dev" => MntOpsRef::Dev(true),
+            "nodev" => MntOpsRef::Dev(false),
+            "exec" => MntOpsRef::Exec(true),
+            "noexec" => MntOpsRef::Exec(false),
+            "suid" => MntOpsRef::Suid(true),
+            "nosuid" => MntOpsRef::Suid(false),
+            "rw" => MntOpsRef::Write(true),
+            "ro" => MntOpsRef::Write(false),
+            extra => MntOpsRef::Extra(extra),
+        }
+    }
+
+    pub fn into_owned(self) -> MntOps {
+        match self {
+            MntOpsRef::Atime(b) => MntOps::Atime(b),
+            MntOpsRef::DirAtime(b) => MntOps::DirAtime(b),
+            MntOpsRef::RelAtime(b) => MntOps::RelAtime(b),
+            MntOpsRef::Dev(b) => MntOps::Dev(b),
+            MntOpsRef::Exec(b) => MntOps::Exec(b),
+            MntOpsRef::Suid(b) => MntOps::Suid(b),
+            MntOpsRef::Write(b) => MntOps::Write(b),
+            MntOpsRef::Extra(extra) => MntOps::Extra(extra.to_string()),
+        }
+    }
+}
+
+impl<'a> fmt::Display for MntOpsRef<'a> {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |b, yes, no| if b { yes } else { no };
+        out.write_str(match *self {
+            MntOpsRef::Atime(b) => flag(b, "atime", "noatime"),
+            MntOpsRef::DirAtime(b) => flag(b, "diratime", "nodiratime"),
+            MntOpsRef::RelAtime(b) => flag(b, "relatime", "norelatime"),
+            MntOpsRef::Dev(b) => flag(b, "dev", "nodev"),
+            MntOpsRef::Exec(b) => flag(b, "exec", "noexec"),
+            MntOpsRef::Suid(b) => flag(b, "suid", "nosuid"),
+            MntOpsRef::Write(b) => flag(b, "rw", "ro"),
+            MntOpsRef::Extra(extra) => extra,
         })
     }
 }
@@ -84,7 +160,7 @@ pub enum MountParam<'a> {
 
 #[derive(Clone, PartialEq, Eq)]
 pub struct MountEntry {
-    pub spec: String,
+    pub spec: OsString,
     pub file: PathBuf,
     pub vfstype: String,
     pub mntops: Vec<MntOps>,
@@ -93,63 +169,181 @@ pub struct MountEntry {
 }
 
 impl MountEntry {
+    /// Get the effective mount options
+    pub fn mntops_set(&self) -> MntOpsSet {
+        MntOpsSet::new(&self.mntops)
+    }
+
     pub fn contains(&self, search: &MountParam) -> bool {
-        match search {
-            &MountParam::Spec(spec) => spec == &self.spec,
-            &MountParam::File(file) => file == &self.file,
-            &MountParam::VfsType(vfstype) => vfstype == &self.vfstype,
-            &MountParam::MntOps(mntops) => self.mntops.contains(mntops),
-            &MountParam::Freq(dumpfield) => dumpfield == &self.freq,
-            &MountParam::PassNo(passno) => passno == &self.passno,
+        match *search {
+            MountParam::Spec(spec) => spec == self.spec,
+            MountParam::File(file) => file == self.file,
+            MountParam::VfsType(vfstype) => vfstype == self.vfstype,
+            MountParam::MntOps(mntops) => self.mntops_set().contains(mntops),
+            MountParam::Freq(dumpfield) => dumpfield == &self.freq,
+            MountParam::PassNo(passno) => passno == &self.passno,
         }
     }
+
+    /// Serialize the fields for an fstab line, with octal escapes for the blanks of `spec` and
+    /// `file`
+    pub(crate) fn to_fields(&self) -> Vec<Vec<u8>> {
+        let mntops: Vec<String> = self.mntops.iter().map(|o| o.to_string()).collect();
+        vec!(
+            escape_octal(self.spec.as_bytes()),
+            escape_octal(self.file.as_os_str().as_bytes()),
+            self.vfstype.clone().into_bytes(),
+            if mntops.is_empty() { b"defaults".to_vec() } else { mntops.join(",").into_bytes() },
+            (self.freq as c_int).to_string().into_bytes(),
+            self.passno.unwrap_or(0).to_string().into_bytes(),
+        )
+    }
+
+    /// Format as a raw fstab line, without the trailing newline
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_fields().join(&b' ')
+    }
 }
 
 impl FromStr for MountEntry {
     type Err = LineError;
 
     fn from_str(line: &str) -> Result<MountEntry, LineError> {
-        let line = line.trim();
-        let mut tokens = line.split_terminator(|s: char| { s == ' ' || s == '\t' })
-            .filter(|s| { s != &""  } );
-        Ok(MountEntry {
-            spec: try!(tokens.next().ok_or(LineError::MissingSpec)).to_string(),
+        MountEntryRef::parse(line).map(MountEntryRef::into_owned)
+    }
+}
+
+/// Get the end of the last field of a line, where the missing fields are expected
+pub(crate) fn fields_end(line: &[u8]) -> usize {
+    line.iter().rposition(|c| !c.is_ascii_whitespace()).map_or(0, |i| i + 1)
+}
+
+/// Split a line into the byte ranges of its blank-separated fields
+pub(crate) fn fields(line: &[u8]) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut pos = 0;
+    iter::from_fn(move || {
+        while pos < line.len() && line[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if pos == line.len() {
+            return None;
+        }
+        let start = pos;
+        while pos < line.len() && !line[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        Some(start..pos)
+    })
+}
+
+/// Decode the octal escapes of a field, only allocating if it has some
+fn decode_field(field: &[u8]) -> Cow<'_, OsStr> {
+    if field.contains(&b'\\') {
+        Cow::Owned(OsString::from_vec(unescape_octal(field)))
+    } else {
+        Cow::Borrowed(OsStr::from_bytes(field))
+    }
+}
+
+/// Borrowed counterpart of `MountEntry`, pointing into the parsed line
+///
+/// The octal escapes (e.g. `\040`) of the `spec` and `file` fields are decoded as for a
+/// `MountEntry`, which only copies these fields when they have some.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MountEntryRef<'a> {
+    pub spec: Cow<'a, OsStr>,
+    pub file: Cow<'a, Path>,
+    pub vfstype: &'a str,
+    mntops: &'a str,
+    pub freq: DumpField,
+    pub passno: PassField,
+}
+
+impl<'a> MountEntryRef<'a> {
+    /// Parse a raw line, which may contain non UTF-8 `spec` and `file` fields
+    pub fn parse<T>(line: &'a T) -> Result<MountEntryRef<'a>, LineError> where T: AsRef<[u8]> + ?Sized {
+        MountEntryRef::parse_with(line.as_ref(), false)
+    }
+
+    /// Parse an fstab line, also accepting the swap entries whose `file` is not a path (e.g. `none`)
+    pub fn parse_fstab<T>(line: &'a T) -> Result<MountEntryRef<'a>, LineError> where T: AsRef<[u8]> + ?Sized {
+        let line = line.as_ref();
+        let is_swap = fields(line).nth(2).map_or(false, |span| &line[span] == b"swap");
+        MountEntryRef::parse_with(line, is_swap)
+    }
+
+    fn parse_with(line: &'a [u8], relative_file: bool) -> Result<MountEntryRef<'a>, LineError> {
+        let end = fields_end(line);
+        let mut tokens = fields(line);
+        let mut next = |kind| tokens.next().map(|span| (&line[span.clone()], span))
+            .ok_or_else(|| LineError::new(kind, end..end));
+        let lossy = |token: &[u8]| String::from_utf8_lossy(token).into_owned();
+        Ok(MountEntryRef {
+            spec: decode_field(next(LineErrorKind::MissingSpec)?.0),
             file: {
-                let file = try!(tokens.next().ok_or(LineError::MissingFile));
-                let path = PathBuf::from(file);
-                if path.is_relative() {
-                    return Err(LineError::InvalidFilePath(file.into()));
+                let (file, span) = next(LineErrorKind::MissingFile)?;
+                if !relative_file && Path::new(OsStr::from_bytes(file)).is_relative() {
+                    return Err(LineError::new(LineErrorKind::InvalidFilePath(lossy(file)), span));
                 }
-                path
+                match decode_field(file) {
+                    Cow::Borrowed(file) => Cow::Borrowed(Path::new(file)),
+                    Cow::Owned(file) => Cow::Owned(PathBuf::from(file)),
+                }
+            },
+            vfstype: {
+                let (vfstype, span) = next(LineErrorKind::MissingVfstype)?;
+                str::from_utf8(vfstype).map_err(|_|
+                    LineError::new(LineErrorKind::InvalidVfstype(lossy(vfstype)), span))?
+            },
+            mntops: {
+                let (mntops, span) = next(LineErrorKind::MissingMntops)?;
+                str::from_utf8(mntops).map_err(|_|
+                    LineError::new(LineErrorKind::InvalidMntops(lossy(mntops)), span))?
             },
-            vfstype: try!(tokens.next().ok_or(LineError::MissingVfstype)).to_string(),
-            mntops: try!(tokens.next().ok_or(LineError::MissingMntops))
-                // FIXME: Handle MntOps errors
-                .split_terminator(',').map(|x| { FromStr::from_str(x).unwrap() }).collect(),
             freq: {
-                let freq = try!(tokens.next().ok_or(LineError::MissingFreq));
-                match FromStr::from_str(freq) {
-                    Ok(0) => DumpField::Ignore,
-                    Ok(1) => DumpField::Backup,
-                    _ => return Err(LineError::InvalidFreq(freq.into())),
+                let (freq, span) = next(LineErrorKind::MissingFreq)?;
+                match str::from_utf8(freq).map(FromStr::from_str) {
+                    Ok(Ok(0)) => DumpField::Ignore,
+                    Ok(Ok(1)) => DumpField::Backup,
+                    _ => return Err(LineError::new(LineErrorKind::InvalidFreq(lossy(freq)), span)),
                 }
             },
             passno: {
-                let passno = try!(tokens.next().ok_or(LineError::MissingPassno));
-                match FromStr::from_str(passno) {
-                    Ok(0) => None,
-                    Ok(f) if f > 0 => Some(f),
-                    _ => return Err(LineError::InvalidPassno(passno.into())),
+                let (passno, span) = next(LineErrorKind::MissingPassno)?;
+                match str::from_utf8(passno).map(FromStr::from_str) {
+                    Ok(Ok(0)) => None,
+                    Ok(Ok(f)) if f > 0 => Some(f),
+                    _ => return Err(LineError::new(LineErrorKind::InvalidPassno(lossy(passno)), span)),
                 }
             },
         })
     }
+
+    /// Iterate over the mount options without allocating
+    pub fn mntops(&self) -> impl Iterator<Item = MntOpsRef<'a>> {
+        self.mntops.split_terminator(',').map(MntOpsRef::parse)
+    }
+
+    pub fn into_owned(self) -> MountEntry {
+        let mntops = self.mntops().map(MntOpsRef::into_owned).collect();
+        MountEntry {
+            spec: self.spec.into_owned(),
+            file: self.file.into_owned(),
+            vfstype: self.vfstype.to_string(),
+            mntops,
+            freq: self.freq,
+            passno: self.passno,
+        }
+    }
 }
 
 
-/// Get a list of all mount points from `root` and beneath using a custom `BufRead`
-pub fn get_submounts_from<T, U>(root: T, iter: MountIter<U>)
-        -> Result<Vec<MountEntry>, ParseError> where T: AsRef<Path>, U: BufRead {
+/// Get a list of all mount points from `root` and beneath using a custom `MountIter`
+///
+/// Any iterator over mount entries can be used, e.g. a lenient one from `MountIter::lenient()`.
+pub fn get_submounts_from<T, U>(root: T, iter: U)
+        -> Result<Vec<MountEntry>, ParseError>
+        where T: AsRef<Path>, U: IntoIterator<Item = Result<MountEntry, ParseError>> {
     let mut ret = vec!();
     for mount in iter {
         match mount {
@@ -162,14 +356,20 @@ pub fn get_submounts_from<T, U>(root: T, iter: MountIter<U>)
     Ok(ret)
 }
 
-/// Get a list of all mount points from `root` and beneath using */proc/mounts*
+/// Get a list of all mount points from `root` and beneath using a snapshot of */proc/mounts*
 pub fn get_submounts<T>(root: T) -> Result<Vec<MountEntry>, ParseError> where T: AsRef<Path> {
-    get_submounts_from(root, try!(MountIter::new_from_proc()))
+    get_submounts_from(root, MountIter::new_from_proc_snapshot()?)
 }
 
-/// Get the mount point for the `target` using a custom `BufRead`
-pub fn get_mount_from<T, U>(target: T, iter: MountIter<U>)
-        -> Result<Option<MountEntry>, ParseError> where T: AsRef<Path>, U: BufRead {
+/// Get the mount point for the `target` using a custom `MountIter`
+///
+/// This offline mode matches the path strings: the last entry whose `file` is a prefix of
+/// `target` wins.  Use `get_mount()` to query the live mount table instead.
+///
+/// Any iterator over mount entries can be used, e.g. a lenient one from `MountIter::lenient()`.
+pub fn get_mount_from<T, U>(target: T, iter: U)
+        -> Result<Option<MountEntry>, ParseError>
+        where T: AsRef<Path>, U: IntoIterator<Item = Result<MountEntry, ParseError>> {
     let mut ret = None;
     for mount in iter {
         match mount {
@@ -183,18 +383,13 @@ pub fn get_mount_from<T, U>(target: T, iter: MountIter<U>)
     Ok(ret)
 }
 
-/// Get the mount point for the `target` using */proc/mounts*
-pub fn get_mount<T>(target: T) -> Result<Option<MountEntry>, ParseError> where T: AsRef<Path> {
-    get_mount_from(target, try!(MountIter::new_from_proc()))
-}
-
 /// Find the potential mount point providing readable or writable access to a path
 ///
 /// Do not check the path existence but its potentially parent mount point.
 pub fn get_mount_writable<T>(target: T, writable: bool) -> Option<MountEntry> where T: AsRef<Path> {
     match get_mount(target) {
         Ok(Some(m)) => {
-            if !writable || m.mntops.contains(&MntOps::Write(writable)) {
+            if !writable || !m.mntops_set().is_read_only() {
                 Some(m)
             } else {
                 None
@@ -205,6 +400,7 @@ pub fn get_mount_writable<T>(target: T, writable: bool) -> Option<MountEntry> wh
 }
 
 pub trait VecMountEntry {
+    #[allow(clippy::ptr_arg)]
     fn remove_overlaps<T>(self, exclude_files: &Vec<T>) -> Self where T: AsRef<Path>;
 }
 
@@ -220,8 +416,8 @@ impl VecMountEntry for Vec<MountEntry> {
             }
             let mut has_overlaps = false;
             'filter: for mount_sorted in sorted.iter() {
-                if exclude_files.iter().skip_while(|x|
-                       AsRef::<Path>::as_ref(&mount_sorted.file) != x.as_ref()).next().is_some() {
+                if exclude_files.iter().any(|x|
+                       AsRef::<Path>::as_ref(&mount_sorted.file) == x.as_ref()) {
                     continue 'filter;
                 }
                 // Check for mount overlaps
@@ -240,6 +436,13 @@ impl VecMountEntry for Vec<MountEntry> {
 }
 
 
+/// Format as an fstab line, the non UTF-8 `spec` and `file` being lossy unlike with `to_bytes()`
+impl fmt::Display for MountEntry {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "{}", String::from_utf8_lossy(&self.to_bytes()))
+    }
+}
+
 impl fmt::Debug for MountEntry {
     fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
         write!(out, "MountEntry {{ spec: {:?}, file: {:?}, vfstype: {:?}, mntops: {:?}, freq: {:?}, passno: {:?} }}",
@@ -249,7 +452,7 @@ impl fmt::Debug for MountEntry {
 
 impl PartialOrd for MountEntry {
     fn partial_cmp(&self, other: &MountEntry) -> Option<Ordering> {
-        self.file.partial_cmp(&other.file)
+        Some(self.cmp(other))
     }
 }
 
@@ -260,22 +463,130 @@ impl Ord for MountEntry {
 }
 
 
+/// Lending iterator over the entries of a mount table, reusing the same line buffer
+pub struct MountRefIter<T> {
+    mtab: T,
+    line: Vec<u8>,
+    nb: usize,
+    source_file: Option<PathBuf>,
+    skip_comments: bool,
+}
+
+impl<T> MountRefIter<T> where T: BufRead {
+    pub fn new(mtab: T) -> MountRefIter<T> {
+        MountRefIter {
+            mtab,
+            line: vec!(),
+            nb: 0,
+            source_file: None,
+            skip_comments: false,
+        }
+    }
+
+    /// Set the mount table path reported by the errors
+    pub fn with_source_file<U>(mut self, path: U) -> MountRefIter<T> where U: Into<PathBuf> {
+        self.source_file = Some(path.into());
+        self
+    }
+
+    /// Read an fstab: skip the blank lines and `#` comments, and accept the swap entries
+    pub fn skip_comments(mut self) -> MountRefIter<T> {
+        self.skip_comments = true;
+        self
+    }
+
+    /// Read the next entry line into the buffer and return its number, starting from 1
+    pub(crate) fn next_line(&mut self) -> Option<io::Result<usize>> {
+        loop {
+            self.line.clear();
+            match self.mtab.read_until(b'\n', &mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    self.nb += 1;
+                    if self.skip_comments {
+                        if let None | Some(&b'#') = self.line.iter().find(|c| !c.is_ascii_whitespace()) {
+                            continue;
+                        }
+                    }
+                    return Some(Ok(self.nb));
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    /// Number of the last read line, starting from 1
+    pub fn line_number(&self) -> usize {
+        self.nb
+    }
+
+    /// Raw content of the last read line, without the trailing newline
+    pub(crate) fn line(&self) -> &[u8] {
+        let end = self.line.iter().rposition(|c| *c != b'\n' && *c != b'\r').map_or(0, |i| i + 1);
+        &self.line[..end]
+    }
+
+    /// Parse the last read line, as an fstab line if comments are skipped
+    pub(crate) fn parse_line(&self) -> Result<MountEntryRef<'_>, LineError> {
+        if self.skip_comments {
+            MountEntryRef::parse_fstab(self.line())
+        } else {
+            MountEntryRef::parse(self.line())
+        }
+    }
+
+    /// Attach the current position to an error
+    pub(crate) fn locate(&self, err: ParseError) -> ParseError {
+        match self.source_file {
+            Some(ref path) => err.with_source_file(path.clone()),
+            None => err,
+        }
+    }
+
+    /// Get the next entry, only valid until the following call
+    pub fn next_entry<'a>(&'a mut self) -> Option<Result<MountEntryRef<'a>, ParseError>> {
+        let nb = match self.next_line()? {
+            Ok(nb) => nb,
+            Err(e) => return Some(Err(self.locate(From::from(e)))),
+        };
+        match self.parse_line() {
+            Ok(m) => Some(Ok(m)),
+            Err(e) => Some(Err(self.locate(ParseError::from_line(e, nb, self.line())))),
+        }
+    }
+}
+
+
 pub struct MountIter<T> {
-    lines: Enumerate<Lines<T>>,
+    pub(crate) entries: MountRefIter<T>,
 }
 
 impl<T> MountIter<T> where T: BufRead {
     pub fn new(mtab: T) -> MountIter<T> {
         MountIter {
-            lines: mtab.lines().enumerate(),
+            entries: MountRefIter::new(mtab),
+        }
+    }
+
+    /// Set the mount table path reported by the errors
+    pub fn with_source_file<U>(self, path: U) -> MountIter<T> where U: Into<PathBuf> {
+        MountIter {
+            entries: self.entries.with_source_file(path),
+        }
+    }
+
+    /// Read an fstab: skip the blank lines and `#` comments, and accept the swap entries
+    pub fn skip_comments(self) -> MountIter<T> {
+        MountIter {
+            entries: self.entries.skip_comments(),
         }
     }
 }
 
 impl MountIter<BufReader<File>> {
     pub fn new_from_proc() -> Result<MountIter<BufReader<File>>, ParseError> {
-        let file = try!(File::open(PROC_MOUNTS));
-        Ok(MountIter::new(BufReader::new(file)))
+        let file = File::open(PROC_MOUNTS).map_err(|e| ParseError::from(e).with_source_file(PROC_MOUNTS))?;
+        Ok(MountIter::new(BufReader::new(file)).with_source_file(PROC_MOUNTS))
     }
 }
 
@@ -283,32 +594,29 @@ impl<T> Iterator for MountIter<T> where T: BufRead {
     type Item = Result<MountEntry, ParseError>;
 
     fn next(&mut self) -> Option<<Self as Iterator>::Item> {
-        match self.lines.next() {
-            Some((nb, line)) => Some(match line {
-                Ok(line) => match <MountEntry as FromStr>::from_str(line.as_ref()) {
-                    Ok(m) => Ok(m),
-                    Err(e) => Err(ParseError::new(format!("Failed at line {}: {}", nb, e))),
-                },
-                Err(e) => Err(From::from(e)),
-            }),
-            None => None,
-        }
+        self.entries.next_entry().map(|entry| entry.map(MountEntryRef::into_owned))
     }
 }
 
 
 #[cfg(test)]
 mod test {
+    use error::{LineErrorKind, ParseErrorKind};
+    use std::borrow::Cow;
+    use std::error::Error;
+    use std::ffi::OsStr;
     use std::fs::File;
     use std::io::{BufReader, BufRead, Cursor};
+    use std::os::unix::ffi::OsStrExt;
     use std::path::{Path, PathBuf};
-    use std::str::FromStr;
-    use super::{DumpField, MntOps, MountEntry, MountIter, MountParam, get_mount_from, get_submounts_from};
+    use std::str::{self, FromStr};
+    use super::{DumpField, MntOps, MntOpsRef, MountEntry, MountEntryRef, MountIter, MountParam, MountRefIter};
+    use super::{get_mount_from, get_submounts_from};
 
     #[test]
     fn test_line_root() {
         let root_ref = MountEntry {
-            spec: "rootfs".to_string(),
+            spec: "rootfs".into(),
             file: PathBuf::from("/"),
             vfstype: "rootfs".to_string(),
             mntops: vec!(MntOps::Write(true)),
@@ -325,7 +633,7 @@ mod test {
     #[test]
     fn test_line_mntops() {
         let root_ref = MountEntry {
-            spec: "rootfs".to_string(),
+            spec: "rootfs".into(),
             file: PathBuf::from("/"),
             vfstype: "rootfs".to_string(),
             mntops: vec!(MntOps::Exec(false), MntOps::Write(true)),
@@ -334,6 +642,151 @@ mod test {
         };
         let from_str = <MountEntry as FromStr>::from_str;
         assert_eq!(from_str("rootfs / rootfs noexec,rw 0 0"), Ok(root_ref.clone()));
+
+        let entry = from_str("rootfs / rootfs ro,noexec,rw 0 0").unwrap();
+        assert!(entry.contains(&MountParam::MntOps(&MntOps::Write(true))));
+        assert!(!entry.contains(&MountParam::MntOps(&MntOps::Write(false))));
+        assert!(entry.contains(&MountParam::MntOps(&MntOps::Suid(true))));
+    }
+
+    #[test]
+    fn test_line_ref() {
+        let line = "tmpfs /run tmpfs rw,nosuid,mode=755 0 2";
+        let entry = MountEntryRef::parse(line).unwrap();
+        assert_eq!(entry.spec, OsStr::new("tmpfs"));
+        assert_eq!(entry.file, Path::new("/run"));
+        assert_eq!(entry.vfstype, "tmpfs");
+        assert_eq!(entry.mntops().collect::<Vec<_>>(),
+                   vec!(MntOpsRef::Write(true), MntOpsRef::Suid(false), MntOpsRef::Extra("mode=755")));
+        assert_eq!(entry.passno, Some(2));
+        assert_eq!(Ok(entry.into_owned()), <MountEntry as FromStr>::from_str(line));
+    }
+
+    #[test]
+    fn test_ref_iter() {
+        let buf = Cursor::new(b"\
+            rootfs / rootfs rw 0 0\n\
+            tmpfs /run tmpfs rw,nosuid 0 0\n\
+            tmpfs run tmpfs rw 0 0\n\
+        ".as_ref());
+        let mut entries = MountRefIter::new(buf.clone());
+        let mut files = vec!();
+        while let Some(entry) = entries.next_entry() {
+            match entry {
+                Ok(e) => files.push(e.file.to_path_buf()),
+                Err(_) => break,
+            }
+        }
+        assert_eq!(files, vec!(PathBuf::from("/"), PathBuf::from("/run")));
+        assert!(entries.next_entry().is_none());
+        assert_eq!(MountIter::new(buf).collect::<Vec<_>>().len(), 3);
+    }
+
+    #[test]
+    fn test_skip_comments() {
+        let buf = Cursor::new(b"\
+            # /etc/fstab\n\
+            \n\
+            rootfs / rootfs rw 0 0\n\
+            \t # swap\n\
+            tmpfs /run tmpfs rw 0 0\n\
+            /dev/sda2 none swap sw 0 0\n\
+        ".as_ref());
+        let err = MountIter::new(buf.clone()).find_map(|m| m.err()).unwrap();
+        assert_eq!(err.line_number(), Some(1));
+        let mut entries = MountRefIter::new(buf).skip_comments();
+        assert_eq!(entries.next_entry().unwrap().unwrap().file, Path::new("/"));
+        assert_eq!(entries.line_number(), 3);
+        assert_eq!(entries.next_entry().unwrap().unwrap().file, Path::new("/run"));
+        assert_eq!(entries.line_number(), 5);
+        assert_eq!(entries.next_entry().unwrap().unwrap().file, Path::new("none"));
+        assert!(entries.next_entry().is_none());
+    }
+
+    #[test]
+    fn test_line_bytes() {
+        let line = b"/dev/sda1 /mnt/\xff\xfe ext4 rw 0 0";
+        let entry = MountEntryRef::parse(line).unwrap().into_owned();
+        assert_eq!(entry.spec, "/dev/sda1");
+        assert_eq!(entry.file, Path::new(OsStr::from_bytes(b"/mnt/\xff\xfe")));
+        assert_eq!(MountEntryRef::parse(b"\xff /mnt ext4 rw 0 0").unwrap().spec.as_bytes(), b"\xff");
+        assert!(MountEntryRef::parse(b"/dev/sda1 /mnt ext\xff rw 0 0").is_err());
+
+        let buf = Cursor::new(b"\
+            rootfs / rootfs rw 0 0\n\
+            /dev/sda1 /mnt/\xff ext4 rw 0 0\n\
+            /dev/sda2 /mnt/\xff/foo ext4 rw 0 0\n\
+        ".as_ref());
+        let mounts = MountIter::new(buf.clone());
+        assert_eq!(mounts.filter(|m| m.is_ok()).count(), 3);
+        let mounts = MountIter::new(buf);
+        let target = Path::new(OsStr::from_bytes(b"/mnt/\xff/bar"));
+        assert_eq!(get_mount_from(target, mounts).unwrap().unwrap().spec, "/dev/sda1");
+    }
+
+    #[test]
+    fn test_ref_unescape() {
+        let line = b"LABEL=my\\040disk /mnt/my\\040disk\\011tab ext4 rw 0 2";
+        let entry = MountEntryRef::parse(line).unwrap();
+        assert_eq!(entry.spec, OsStr::new("LABEL=my disk"));
+        assert_eq!(entry.file, Path::new("/mnt/my disk\ttab"));
+        assert_eq!(Ok(entry.clone().into_owned()), MountEntry::from_str(str::from_utf8(line).unwrap()));
+        let entry = MountEntryRef::parse(b"/dev/sda1 /mnt/\xff ext4 rw 0 2").unwrap();
+        assert!(matches!(entry.spec, Cow::Borrowed(_)));
+        assert!(matches!(entry.file, Cow::Borrowed(_)));
+        assert!(MountEntryRef::parse(b"/dev/sda1 \\057mnt ext4 rw 0 2").is_err());
+    }
+
+    #[test]
+    fn test_line_display() {
+        let line = "LABEL=my\\040disk /mnt/my\\040disk\\011tab ext4 ro,noexec 0 2";
+        let entry = MountEntry::from_str(line).unwrap();
+        assert_eq!(entry.spec, "LABEL=my disk");
+        assert_eq!(entry.file, Path::new("/mnt/my disk\ttab"));
+        assert_eq!(entry.to_string(), line);
+        let entry = MountEntry::from_str("tmpfs /tmp tmpfs defaults 1 0").unwrap();
+        assert_eq!(entry.to_string(), "tmpfs /tmp tmpfs defaults 1 0");
+        let entry = MountEntryRef::parse(b"/dev/sda1 /mnt/\xff\\040x ext4 rw 0 0").unwrap().into_owned();
+        assert_eq!(entry.to_bytes(), b"/dev/sda1 /mnt/\xff\\040x ext4 rw 0 0".to_vec());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let buf = Cursor::new(b"rootfs / rootfs rw 0 0\ntmpfs /run tmpfs rw 0\n".as_ref());
+        let err = MountIter::new(buf).with_source_file("/etc/fstab").find_map(|m| m.err()).unwrap();
+        assert_eq!(err.line_number(), Some(2));
+        assert_eq!(err.source_file(), Some(Path::new("/etc/fstab")));
+        assert_eq!(err.line(), Some(b"tmpfs /run tmpfs rw 0".as_ref()));
+        match *err.kind() {
+            ParseErrorKind::Line(ref e) => assert_eq!(*e.kind(), LineErrorKind::MissingPassno),
+            ref kind => panic!("Unexpected error kind: {:?}", kind),
+        }
+        assert_eq!(err.to_string(), "Mount parsing: Failed to parse /etc/fstab at line 2");
+        assert_eq!(err.source().unwrap().to_string(), "Line parsing: Missing field #6 (passno)");
+    }
+
+    #[test]
+    fn test_line_span() {
+        let parse = |line: &str| MountEntryRef::parse(line).unwrap_err();
+        let err = parse("tmpfs /run tmpfs rw x 0");
+        assert_eq!(*err.kind(), LineErrorKind::InvalidFreq("x".into()));
+        assert_eq!(err.span(), 20..21);
+        assert_eq!(parse("  tmpfs run tmpfs rw 0 0").span(), 8..11);
+        assert_eq!(parse("tmpfs /run tmpfs rw 0  \n").span(), 21..21);
+        assert_eq!(err.render("tmpfs /run tmpfs rw x 0"), concat!(
+            "error: Line parsing: Bad field #5 (dump) value: x\n",
+            " |\n",
+            " | tmpfs /run tmpfs rw x 0\n",
+            " |                     ^"));
+
+        let buf = Cursor::new(b"rootfs / rootfs rw 0 0\ntmpfs\t/run\ttmpfs\trw\t0\t-1\n".as_ref());
+        let err = MountIter::new(buf).with_source_file("/etc/fstab").find_map(|m| m.err()).unwrap();
+        assert_eq!(err.render(), concat!(
+            "error: Line parsing: Bad field #6 (passno) value: -1\n",
+            "  --> /etc/fstab:2:23\n",
+            "  |\n",
+            "2 | tmpfs\t/run\ttmpfs\trw\t0\t-1\n",
+            "  |      \t    \t     \t  \t \t^^"));
     }
 
     fn test_file<T>(path: T) -> Result<(), String> where T: AsRef<Path> {
@@ -367,6 +820,8 @@ mod test {
         assert!(from_str("rootfs foo rootfs rw 0 0").is_err());
         // Should fail for a swap pseudo-mount
         assert!(from_str("/dev/mapper/swap none swap sw 0 0").is_err());
+        assert_eq!(MountEntryRef::parse_fstab("/dev/mapper/swap none swap sw 0 0").unwrap().file, Path::new("none"));
+        assert!(MountEntryRef::parse_fstab("rootfs foo rootfs rw 0 0").is_err());
     }
 
     #[test]
@@ -384,7 +839,7 @@ mod test {
         ".as_ref());
         // FIXME: Append /dev/dm-0 / ext4 rw,relatime,errors=remount-ro,data=ordered 0 0\n\
         let mount_vartmp = MountEntry {
-            spec: "/dev/mapper/foo-tmp".to_string(),
+            spec: "/dev/mapper/foo-tmp".into(),
             file: PathBuf::from("/var/tmp"),
             vfstype: "ext4".to_string(),
             mntops: vec![Write(true), RelAtime(true), Extra("data=ordered".to_string())],
@@ -392,7 +847,7 @@ mod test {
             passno: None
         };
         let mount_root = MountEntry {
-            spec: "rootfs".to_string(),
+            spec: "rootfs".into(),
             file: PathBuf::from("/"),
             vfstype: "rootfs".to_string(),
             mntops: vec![Write(true)],
@@ -400,7 +855,7 @@ mod test {
             passno: None
         };
         let mount_sysfs = MountEntry {
-            spec: "sysfs".to_string(),
+            spec: "sysfs".into(),
             file: PathBuf::from("/sys"),
             vfstype: "sysfs".to_string(),
             mntops: vec![Write(true), Suid(false), Dev(false), Exec(false), RelAtime(true)],
@@ -408,7 +863,7 @@ mod test {
             passno: None
         };
         let mount_tmp = MountEntry {
-            spec: "tmpfs".to_string(),
+            spec: "tmpfs".into(),
             file: PathBuf::from("/sys/fs/cgroup"),
             vfstype: "tmpfs".to_string(),
             mntops: vec![Write(false), Suid(false), Dev(false), Exec(false), Extra("mode=755".to_string())],
@@ -420,7 +875,7 @@ mod test {
             mount_sysfs.clone(),
             mount_tmp.clone(),
             MountEntry {
-                spec: "udev".to_string(),
+                spec: "udev".into(),
                 file: PathBuf::from("/dev"),
                 vfstype: "devtmpfs".to_string(),
                 mntops: vec![Write(true), RelAtime(true), Extra("size=10240k".to_string()), Extra("nr_inodes=505357".to_string()), Extra("mode=755".to_string())],
@@ -428,7 +883,7 @@ mod test {
                 passno: None
             },
             MountEntry {
-                spec: "tmpfs".to_string(),
+                spec: "tmpfs".into(),
                 file: PathBuf::from("/run"),
                 vfstype: "tmpfs".to_string(),
                 mntops: vec![Write(true), Suid(false), RelAtime(true), Extra("size=809928k".to_string()), Extra("mode=755".to_string())],
@@ -449,19 +904,19 @@ mod test {
         assert_eq!(get_mount_from("/var/", mounts).ok(), Some(Some(mount_root.clone())));
 
         // search
-        let mut mounts = MountIter::new(buf.clone()).map(|m| m.ok().unwrap());;
+        let mut mounts = MountIter::new(buf.clone()).map(|m| m.ok().unwrap());
         assert_eq!(mounts.find(|m|
                m.contains(&MountParam::Spec("rootfs"))
             ).unwrap(), mount_root.clone());
-        let mut mounts = MountIter::new(buf.clone()).map(|m| m.ok().unwrap());;
+        let mut mounts = MountIter::new(buf.clone()).map(|m| m.ok().unwrap());
         assert_eq!(mounts.find(|m|
                 m.contains(&MountParam::File(Path::new("/")))
             ).unwrap(), mount_root.clone());
-        let mut mounts = MountIter::new(buf.clone()).map(|m| m.ok().unwrap());;
+        let mut mounts = MountIter::new(buf.clone()).map(|m| m.ok().unwrap());
         assert_eq!(mounts.find(|m|
                 m.contains(&MountParam::VfsType("tmpfs"))
             ).unwrap(), mount_tmp.clone());
-        let mut mounts = MountIter::new(buf.clone()).map(|m| m.ok().unwrap());;
+        let mut mounts = MountIter::new(buf.clone()).map(|m| m.ok().unwrap());
         let mnt_ops = [MntOps::Write(true), MntOps::Suid(false), MntOps::Dev(false), MntOps::Exec(false)];
         assert_eq!(mounts.find(|m| {
                 mnt_ops.iter().all( |o| m.contains(&MountParam::MntOps(o)) )
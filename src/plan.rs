@@ -0,0 +1,254 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+extern crate libc;
+
+use fsclass::FsClass;
+use parse::{MntOps, MountEntry};
+use self::libc::c_int;
+use source::{DeviceResolver, MountSource};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Entry to mount at boot
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MountStep {
+    pub entry: MountEntry,
+    /// A failure to mount must not stop the boot (`nofail`)
+    pub nofail: bool,
+}
+
+/// Mount order of an fstab, parents coming before their children
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MountPlan {
+    /// Mounts to do first
+    pub local: Vec<MountStep>,
+    /// Mounts to do once the network is up, i.e. `_netdev` ones, network filesystems and their
+    /// children
+    pub network: Vec<MountStep>,
+    /// `noauto` entries, which are not mounted at boot
+    pub skipped: Vec<MountEntry>,
+}
+
+/// Entries to check in parallel, except for the ones on the same physical device
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FsckPass {
+    pub passno: c_int,
+    /// Entries of the same physical device, to check one after the other
+    pub groups: Vec<Vec<MountEntry>>,
+}
+
+fn has_extra(entry: &MountEntry, extra: &str) -> bool {
+    entry.mntops_set().contains(&MntOps::Extra(extra.into()))
+}
+
+fn needs_network(entry: &MountEntry) -> bool {
+    has_extra(entry, "_netdev") || FsClass::from_known(&entry.vfstype) == Some(FsClass::Network)
+}
+
+/// Check if `entry` must be mounted after `other`, i.e. into it or from a bind source in it
+fn depends_on(entry: &MountEntry, other: &MountEntry) -> bool {
+    if entry.file != other.file && entry.file.starts_with(&other.file) {
+        return true;
+    }
+    let is_bind = has_extra(entry, "bind") || has_extra(entry, "rbind");
+    is_bind && Path::new(&entry.spec).starts_with(&other.file)
+}
+
+/// Sort the `entries` indexes with a stable topological sort on their dependencies
+///
+/// Entries are taken in the fstab order as soon as their dependencies are done.  A dependency
+/// cycle is broken at its first entry.
+fn mount_order(entries: &[MountEntry]) -> Vec<usize> {
+    let mut order: Vec<usize> = vec!();
+    let mut done = vec!(false; entries.len());
+    while order.len() < entries.len() {
+        let is_ready = |i: usize| !done[i] && entries.iter().enumerate()
+            .all(|(j, other)| j == i || done[j] || !depends_on(&entries[i], other));
+        let next = (0..entries.len()).find(|&i| is_ready(i))
+            .or_else(|| (0..entries.len()).find(|&i| !done[i]))
+            .unwrap();
+        done[next] = true;
+        order.push(next);
+    }
+    order
+}
+
+/// Order the fstab `entries` for mounting at boot
+///
+/// The fstab order is kept, except that an entry always comes after the ones it is mounted into
+/// and the ones holding its bind source.
+pub fn plan_mounts(entries: &[MountEntry]) -> MountPlan {
+    let mut plan = MountPlan::default();
+    let mut network: Vec<&MountEntry> = vec!();
+    for i in mount_order(entries) {
+        let entry = &entries[i];
+        if entry.mntops_set().is_noauto() {
+            plan.skipped.push(entry.clone());
+            continue;
+        }
+        let step = MountStep {
+            entry: entry.clone(),
+            nofail: has_extra(entry, "nofail"),
+        };
+        if needs_network(entry) || network.iter().any(|n| depends_on(entry, n)) {
+            network.push(entry);
+            plan.network.push(step);
+        } else {
+            plan.local.push(step);
+        }
+    }
+    plan
+}
+
+/// Strip the partition number of a device path, as `fsck(8)` does for the common disks
+fn base_device(path: &Path) -> PathBuf {
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(n) => n,
+        None => return path.to_path_buf(),
+    };
+    let trimmed = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    let disk = match trimmed.strip_suffix('p') {
+        Some(disk) if name.starts_with("nvme") || name.starts_with("mmcblk") => disk,
+        _ if ["sd", "hd", "vd", "xvd"].iter().any(|p| name.starts_with(p)) => trimmed,
+        _ => name,
+    };
+    path.with_file_name(disk)
+}
+
+/// Identify the physical device holding `entry`
+fn physical_device(resolver: &DeviceResolver, entry: &MountEntry) -> PathBuf {
+    let source = entry.mount_source();
+    if let Ok(Some(dev)) = resolver.device_id(&source) {
+        // The parent of a partition in sysfs is its disk
+        if let Ok(path) = fs::canonicalize(format!("/sys/dev/block/{}", dev)) {
+            if path.join("partition").exists() {
+                return path.parent().unwrap_or(&path).to_path_buf();
+            }
+            return path;
+        }
+    }
+    match source {
+        MountSource::Device(ref path) => base_device(path),
+        _ => PathBuf::from(&entry.spec),
+    }
+}
+
+/// Group the fstab `entries` into `fsck(8)` passes, ordered by `passno`
+///
+/// Entries with a zero `passno`, `noauto` ones and the ones without a block device are skipped.
+pub fn plan_fsck(entries: &[MountEntry], resolver: &DeviceResolver) -> Vec<FsckPass> {
+    let mut passes: Vec<FsckPass> = vec!();
+    let mut devices: Vec<(c_int, PathBuf, usize)> = vec!();
+    for entry in entries {
+        let passno = match entry.passno {
+            Some(p) if p > 0 => p,
+            _ => continue,
+        };
+        // Types missing from the table are checked if asked to
+        let checkable = FsClass::from_known(&entry.vfstype).map_or(true, |c| c == FsClass::BlockBacked);
+        if entry.mntops_set().is_noauto() || !checkable {
+            continue;
+        }
+        let index = match passes.iter().position(|p| p.passno == passno) {
+            Some(i) => i,
+            None => {
+                passes.push(FsckPass {
+                    passno,
+                    groups: vec!(),
+                });
+                passes.len() - 1
+            }
+        };
+        let device = physical_device(resolver, entry);
+        let pass = &mut passes[index];
+        match devices.iter().find(|&&(p, ref d, _)| p == passno && *d == device) {
+            Some(&(_, _, group)) => pass.groups[group].push(entry.clone()),
+            None => {
+                devices.push((passno, device, pass.groups.len()));
+                pass.groups.push(vec!(entry.clone()));
+            }
+        }
+    }
+    passes.sort_by_key(|p| p.passno);
+    passes
+}
+
+
+#[cfg(test)]
+mod test {
+    use parse::MountEntry;
+    use source::DeviceResolver;
+    use std::path::Path;
+    use super::{MountStep, base_device, plan_fsck, plan_mounts};
+
+    fn entries(fstab: &str) -> Vec<MountEntry> {
+        fstab.lines().map(|l| l.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_plan_mounts() {
+        let plan = plan_mounts(&entries("\
+            /dev/sda3 /home/shared ext4 nofail 0 2
+            /dev/sda2 /home ext4 defaults 0 2
+            /dev/sda1 / ext4 defaults 0 1
+            server:/export /srv/nfs nfs defaults 0 0
+            /dev/sdb1 /srv/nfs/local ext4 defaults 0 2
+            /dev/sdc1 /media/usb vfat noauto 0 0
+            /dev/sdd1 /srv/iscsi ext4 _netdev 0 0"));
+        let files = |steps: &[MountStep]| steps.iter().map(|s| s.entry.file.to_string_lossy().into_owned()).collect::<Vec<_>>();
+        assert_eq!(files(&plan.local), vec!("/", "/home", "/home/shared"));
+        assert!(plan.local[2].nofail);
+        assert!(!plan.local[1].nofail);
+        assert_eq!(files(&plan.network), vec!("/srv/nfs", "/srv/nfs/local", "/srv/iscsi"));
+        assert_eq!(plan.skipped.len(), 1);
+    }
+
+    #[test]
+    fn test_plan_bind() {
+        let plan = plan_mounts(&entries("\
+            /srv/data/www /var/www none bind 0 0
+            /dev/sda1 / ext4 defaults 0 1
+            /srv/nfs/pub /var/pub none rbind 0 0
+            /dev/sda2 /srv/data ext4 defaults 0 2
+            server:/export /srv/nfs nfs defaults 0 0"));
+        let files = |steps: &[MountStep]| steps.iter().map(|s| s.entry.file.to_string_lossy().into_owned()).collect::<Vec<_>>();
+        assert_eq!(files(&plan.local), vec!("/", "/srv/data", "/var/www"));
+        assert_eq!(files(&plan.network), vec!("/srv/nfs", "/var/pub"));
+    }
+
+    #[test]
+    fn test_plan_fsck() {
+        let passes = plan_fsck(&entries("\
+            /dev/nonexistent/sda1 / ext4 defaults 0 1
+            /dev/nonexistent/sda2 /home ext4 defaults 0 2
+            /dev/nonexistent/sdb1 /srv ext4 defaults 0 2
+            /dev/nonexistent/sda3 /var ext4 defaults 0 2
+            /dev/nonexistent/sdc1 /media/usb vfat noauto 0 2
+            tmpfs /tmp tmpfs defaults 0 2
+            /dev/nonexistent/sdd1 /data ext4 defaults 0 0"), &DeviceResolver::new());
+        assert_eq!(passes.len(), 2);
+        assert_eq!(passes[0].passno, 1);
+        assert_eq!(passes[0].groups.len(), 1);
+        let groups: Vec<Vec<_>> = passes[1].groups.iter()
+            .map(|g| g.iter().map(|m| m.file.to_string_lossy().into_owned()).collect()).collect();
+        assert_eq!(groups, vec!(vec!("/home", "/var"), vec!("/srv")));
+
+        assert_eq!(base_device(Path::new("/dev/sda12")), Path::new("/dev/sda"));
+        assert_eq!(base_device(Path::new("/dev/nvme0n1p2")), Path::new("/dev/nvme0n1"));
+        assert_eq!(base_device(Path::new("/dev/mmcblk0p1")), Path::new("/dev/mmcblk0"));
+        assert_eq!(base_device(Path::new("/dev/nvme0n1")), Path::new("/dev/nvme0n1"));
+        assert_eq!(base_device(Path::new("/dev/md0")), Path::new("/dev/md0"));
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+extern crate libc;
+
+use error::*;
+use mountinfo::{DeviceId, MountInfoEntry, MountTable};
+use parse::MountEntry;
+use self::libc::{c_int, c_uint};
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+/// Call `statx(2)` on `path`, following symlinks unless `flags` says otherwise
+pub(crate) fn statx<T>(path: T, flags: c_int, mask: c_uint) -> io::Result<libc::statx> where T: AsRef<Path> {
+    let path = CString::new(path.as_ref().as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut buf: libc::statx = unsafe { mem::zeroed() };
+    match unsafe { libc::statx(libc::AT_FDCWD, path.as_ptr(), flags, mask, &mut buf) } {
+        0 => Ok(buf),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Get the mount ID of `path`, if the kernel supports it
+pub(crate) fn mount_id<T>(path: T) -> io::Result<Option<u64>> where T: AsRef<Path> {
+    match statx(path, 0, libc::STATX_MNT_ID) {
+        Ok(stx) if stx.stx_mask & libc::STATX_MNT_ID != 0 => Ok(Some(stx.stx_mnt_id)),
+        Ok(_) => Ok(None),
+        Err(ref e) if e.raw_os_error() == Some(libc::ENOSYS) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+impl MountTable {
+    /// Get the mount containing the `target` path
+    ///
+    /// The mount is identified with the mount ID from `statx(2)`, or with the device number from
+    /// `stat(2)` on older kernels.  Symlinks are followed.
+    pub fn resolve<T>(&self, target: T) -> io::Result<Option<&MountInfoEntry>> where T: AsRef<Path> {
+        if let Some(id) = mount_id(&target)? {
+            return Ok(self.get_by_id(id));
+        }
+        let dev = DeviceId::from(fs::metadata(&target)?.dev());
+        let mut candidates = self.find_by_dev(dev).peekable();
+        let first = candidates.next();
+        if candidates.peek().is_none() {
+            return Ok(first);
+        }
+        // Several mounts of the same filesystem: pick the visible one containing the target
+        let target = fs::canonicalize(&target)?;
+        Ok(first.into_iter().chain(candidates)
+            .filter(|m| target.starts_with(&m.mount_point))
+            .max_by_key(|m| m.mount_point.components().count()))
+    }
+}
+
+/// Get the mount point for the `target` by identifying its mount with `statx(2)`
+///
+/// If `target` doesn't exist, its nearest existing parent is used instead.  Use
+/// `get_mount_from()` to look up an offline mount table by string prefix matching.
+pub fn get_mount<T>(target: T) -> Result<Option<MountEntry>, ParseError> where T: AsRef<Path> {
+    let table = MountTable::new_from_proc()?;
+    with_existing(target.as_ref(), |p| table.resolve(p))
+        .map(|m| m.map(MountInfoEntry::to_mount_entry))
+        .map_err(|e| ParseError::access(target.as_ref(), e))
+}
+
+/// Call `f` on `path`, or on its nearest existing parent if it doesn't exist
+fn with_existing<'a, F, R>(path: &'a Path, mut f: F) -> io::Result<R> where F: FnMut(&'a Path) -> io::Result<R> {
+    let mut path = path;
+    loop {
+        match f(path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound && path != Path::new(".") => {
+                path = match path.parent() {
+                    Some(p) if p != Path::new("") => p,
+                    _ => Path::new("."),
+                };
+            }
+            ret => return ret,
+        }
+    }
+}
+
+/// Check if `a` and `b` are on the same mount, i.e. if `rename(2)` and `link(2)` between them
+/// don't fail with `EXDEV`
+///
+/// Two bind mounts of the same filesystem are different mounts.  Paths which don't exist yet are
+/// checked through their nearest existing parent.  Symlinks are followed.
+pub fn same_mount<T, U>(a: T, b: U) -> Result<bool, ParseError> where T: AsRef<Path>, U: AsRef<Path> {
+    let (a, b) = (a.as_ref(), b.as_ref());
+    let locate = |path: &Path, e| ParseError::access(path, e);
+    let id_a = with_existing(a, mount_id).map_err(|e| locate(a, e))?;
+    let id_b = with_existing(b, mount_id).map_err(|e| locate(b, e))?;
+    if let (Some(id_a), Some(id_b)) = (id_a, id_b) {
+        return Ok(id_a == id_b);
+    }
+    let table = MountTable::new_from_proc()?;
+    let mount_a = with_existing(a, |p| table.resolve(p)).map_err(|e| locate(a, e))?;
+    let mount_b = with_existing(b, |p| table.resolve(p)).map_err(|e| locate(b, e))?;
+    Ok(match (mount_a, mount_b) {
+        (Some(mount_a), Some(mount_b)) => mount_a.mount_id == mount_b.mount_id,
+        _ => false,
+    })
+}
+
+/// Check if `a` and `b` are on the same filesystem, even if through different bind mounts
+///
+/// Paths which don't exist yet are checked through their nearest existing parent.  Symlinks are
+/// followed.
+pub fn same_filesystem<T, U>(a: T, b: U) -> Result<bool, ParseError> where T: AsRef<Path>, U: AsRef<Path> {
+    let (a, b) = (a.as_ref(), b.as_ref());
+    let dev = |path: &Path| with_existing(path, |p| fs::metadata(p).map(|m| m.dev()))
+        .map_err(|e| ParseError::access(path, e));
+    Ok(dev(a)? == dev(b)?)
+}
+
+/// Check if `path` is the root of a mount, for directory and file bind mounts alike
+///
+/// The `STATX_ATTR_MOUNT_ROOT` attribute is used when available, otherwise the mount IDs or
+/// devices of `path` and its parent are compared, and finally the mount table is looked up.
+/// Symlinks are followed.
+pub fn is_mount_point<T>(path: T) -> Result<bool, ParseError> where T: AsRef<Path> {
+    let locate = |e: io::Error| ParseError::access(path.as_ref(), e);
+    let stx = match statx(&path, 0, libc::STATX_MNT_ID) {
+        Ok(stx) => Some(stx),
+        Err(ref e) if e.raw_os_error() == Some(libc::ENOSYS) => None,
+        Err(e) => return Err(locate(e)),
+    };
+    let mount_root = libc::STATX_ATTR_MOUNT_ROOT as u64;
+    if let Some(ref stx) = stx {
+        if stx.stx_attributes_mask & mount_root != 0 {
+            return Ok(stx.stx_attributes & mount_root != 0);
+        }
+    }
+
+    let path = fs::canonicalize(&path).map_err(locate)?;
+    let parent = match path.parent() {
+        Some(p) => p,
+        None => return Ok(true),
+    };
+    if let Some(stx) = stx.filter(|s| s.stx_mask & libc::STATX_MNT_ID != 0) {
+        if let Some(id) = mount_id(parent).map_err(locate)? {
+            return Ok(id != stx.stx_mnt_id);
+        }
+    }
+    let dev = |p: &Path| fs::metadata(p).map(|m| m.dev()).map_err(locate);
+    if dev(&path)? != dev(parent)? {
+        return Ok(true);
+    }
+    // Bind mount from the same filesystem
+    let table = MountTable::new_from_proc()?;
+    Ok(table.entries().iter().any(|m| m.mount_point == path))
+}
+
+
+#[cfg(test)]
+mod test {
+    use error::ParseErrorKind;
+    use mountinfo::MountTable;
+    use std::env;
+    use std::path::Path;
+    use std::fs::{self, File};
+    use std::process;
+    use super::{get_mount, is_mount_point, same_filesystem, same_mount};
+
+    #[test]
+    fn test_resolve() {
+        let table = MountTable::new_from_proc().unwrap();
+        assert_eq!(table.resolve("/proc/self").unwrap().unwrap().vfstype, "proc");
+        // The working directory is relative to the current mount
+        let cwd = env::current_dir().unwrap();
+        assert_eq!(table.resolve(".").unwrap(), table.resolve(&cwd).unwrap());
+        assert!(table.resolve("/nonexistent/foo").is_err());
+    }
+
+    #[test]
+    fn test_get_mount() {
+        assert_eq!(get_mount("/proc/self/nonexistent").unwrap().unwrap().file, Path::new("/proc"));
+        assert!(get_mount("/").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_is_mount_point() {
+        assert!(is_mount_point("/").unwrap());
+        assert!(is_mount_point("/proc").unwrap());
+        assert!(is_mount_point("/proc/self/..").unwrap());
+        assert!(!is_mount_point("/proc/self/fd").unwrap());
+        let file = env::temp_dir().join(format!("mnt-mount-point-{}", process::id()));
+        File::create(&file).unwrap();
+        let is_mount = is_mount_point(&file);
+        fs::remove_file(&file).unwrap();
+        assert!(!is_mount.unwrap());
+        let err = is_mount_point("/nonexistent").unwrap_err();
+        match *err.kind() {
+            ParseErrorKind::Access(ref path, _) => assert_eq!(path, Path::new("/nonexistent")),
+            ref kind => panic!("Unexpected error: {:?}", kind),
+        }
+        assert_eq!(err.to_string(), "Mount lookup: Failed to access /nonexistent");
+    }
+
+    #[test]
+    fn test_same_mount() {
+        assert!(same_mount("/proc/self", "/proc/nonexistent/foo").unwrap());
+        assert!(!same_mount("/proc", "/").unwrap());
+        assert!(same_filesystem("/proc/self", "/proc/1").unwrap());
+        assert!(!same_filesystem("/proc", "/").unwrap());
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+extern crate libc;
+
+use error::*;
+use parse::{MountIter, PROC_MOUNTS};
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// Maximum number of reads before giving up on a mount table which keeps changing
+const SNAPSHOT_ATTEMPTS: usize = 16;
+
+/// Check if the mount namespace changed since `file` was opened
+///
+/// The kernel flags a mount table file with `POLLPRI` once a mount event occurred. Return `None`
+/// if the file can't be polled.
+fn has_changed(file: &File) -> Option<bool> {
+    let mut pfd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLPRI,
+        revents: 0,
+    };
+    match unsafe { libc::poll(&mut pfd, 1, 0) } {
+        -1 => None,
+        _ => Some(pfd.revents & (libc::POLLPRI | libc::POLLERR) != 0),
+    }
+}
+
+/// Read a consistent snapshot of a mount table file
+///
+/// The whole file is read in one pass and the read is retried if the mount table changed in the
+/// meantime.  When the file can't be polled, the snapshot is validated by reading it again until
+/// two consecutive reads are identical.
+pub fn read_snapshot<T>(path: T) -> io::Result<Vec<u8>> where T: AsRef<Path> {
+    let mut previous: Option<Vec<u8>> = None;
+    for _ in 0..SNAPSHOT_ATTEMPTS {
+        let mut file = File::open(path.as_ref())?;
+        let mut buf = match previous {
+            Some(ref p) => Vec::with_capacity(p.len()),
+            None => vec!(),
+        };
+        file.read_to_end(&mut buf)?;
+        match has_changed(&file) {
+            Some(false) => return Ok(buf),
+            Some(true) => previous = None,
+            None => {
+                if previous.as_ref() == Some(&buf) {
+                    return Ok(buf);
+                }
+                previous = Some(buf);
+            }
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::Other, format!("The mount table kept changing while reading {}",
+                                                     path.as_ref().display())))
+}
+
+impl MountIter<Cursor<Vec<u8>>> {
+    /// Iterate over a consistent snapshot of the mount table file `path`
+    pub fn new_from_snapshot<T>(path: T) -> Result<MountIter<Cursor<Vec<u8>>>, ParseError>
+            where T: AsRef<Path> {
+        let snapshot = read_snapshot(&path).map_err(|e| ParseError::from(e).with_source_file(path.as_ref()))?;
+        Ok(MountIter::new(Cursor::new(snapshot)).with_source_file(path.as_ref()))
+    }
+
+    /// Iterate over a consistent snapshot of */proc/mounts*
+    pub fn new_from_proc_snapshot() -> Result<MountIter<Cursor<Vec<u8>>>, ParseError> {
+        MountIter::new_from_snapshot(PROC_MOUNTS)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::process;
+    use super::read_snapshot;
+    use parse::MountIter;
+
+    #[test]
+    fn test_snapshot_file() {
+        let path = env::temp_dir().join(format!("mnt-snapshot-{}", process::id()));
+        let content = b"rootfs / rootfs rw 0 0\ntmpfs /tmp tmpfs rw 0 0\n";
+        File::create(&path).unwrap().write_all(content).unwrap();
+        let snapshot = read_snapshot(&path);
+        let mounts = MountIter::new_from_snapshot(&path).unwrap().count();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(snapshot.unwrap(), content.to_vec());
+        assert_eq!(mounts, 2);
+    }
+
+    #[test]
+    fn test_snapshot_proc() {
+        let mut mounts = MountIter::new_from_proc_snapshot().unwrap();
+        assert!(mounts.all(|m| m.is_ok()));
+    }
+}
@@ -0,0 +1,292 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use mountinfo::{DeviceId, MountInfoEntry};
+use parse::MountEntry;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+const DISK_DIR: &str = "/dev/disk";
+
+/// What a mount entry `spec` refers to
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MountSource {
+    /// Device path, e.g. `/dev/mapper/foo-tmp`
+    Device(PathBuf),
+    Uuid(String),
+    Label(String),
+    PartUuid(String),
+    PartLabel(String),
+    /// Hardware ID, as in */dev/disk/by-id*
+    Id(String),
+    /// Any other source, e.g. `tmpfs` or `server:/export`
+    Other(OsString),
+}
+
+/// Tags and their */dev/disk* directory
+const TAGS: &[(&str, &str)] = &[
+    ("UUID", "by-uuid"),
+    ("LABEL", "by-label"),
+    ("PARTUUID", "by-partuuid"),
+    ("PARTLABEL", "by-partlabel"),
+    ("ID", "by-id"),
+];
+
+impl MountSource {
+    pub fn parse<T>(spec: &T) -> MountSource where T: AsRef<OsStr> + ?Sized {
+        let spec = spec.as_ref();
+        if spec.as_bytes().starts_with(b"/") {
+            return MountSource::Device(PathBuf::from(spec));
+        }
+        let (tag, value) = match spec.to_str().and_then(|s| s.split_once('=')) {
+            Some(t) => t,
+            None => return MountSource::Other(spec.to_os_string()),
+        };
+        let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value).to_string();
+        match tag {
+            "UUID" => MountSource::Uuid(value),
+            "LABEL" => MountSource::Label(value),
+            "PARTUUID" => MountSource::PartUuid(value),
+            "PARTLABEL" => MountSource::PartLabel(value),
+            "ID" => MountSource::Id(value),
+            _ => MountSource::Other(spec.to_os_string()),
+        }
+    }
+
+    /// Get the tag name and its value
+    pub fn tag(&self) -> Option<(&'static str, &str)> {
+        let (index, value) = match *self {
+            MountSource::Uuid(ref v) => (0, v),
+            MountSource::Label(ref v) => (1, v),
+            MountSource::PartUuid(ref v) => (2, v),
+            MountSource::PartLabel(ref v) => (3, v),
+            MountSource::Id(ref v) => (4, v),
+            _ => return None,
+        };
+        Some((TAGS[index].0, value))
+    }
+}
+
+impl<'a> From<&'a OsStr> for MountSource {
+    fn from(spec: &'a OsStr) -> MountSource {
+        MountSource::parse(spec)
+    }
+}
+
+impl fmt::Display for MountSource {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MountSource::Device(ref path) => write!(out, "{}", path.display()),
+            MountSource::Other(ref spec) => write!(out, "{}", spec.to_string_lossy()),
+            _ => {
+                let (tag, value) = self.tag().unwrap();
+                write!(out, "{}={}", tag, value)
+            }
+        }
+    }
+}
+
+impl MountEntry {
+    /// Get what the `spec` field refers to
+    pub fn mount_source(&self) -> MountSource {
+        MountSource::parse(&self.spec)
+    }
+}
+
+impl MountInfoEntry {
+    /// Get the device node given as the mount source, if any
+    ///
+    /// Its device number may differ from `dev` because filesystems such as btrfs use an
+    /// anonymous one for their mounts.
+    pub(crate) fn source_device(&self) -> Option<PathBuf> {
+        match MountSource::parse(&self.source) {
+            MountSource::Device(path) if path.starts_with("/dev") => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// Encode a tag value as udev does for the */dev/disk* symlink names
+///
+/// ASCII alphanumerics, `#+-.:=@_` and multibyte UTF-8 characters are kept, as with
+/// `blkid_encode_string()`, while the other bytes are escaped as `\xNN`.
+fn encode_tag(value: &str) -> String {
+    let mut ret = String::new();
+    for c in value.chars() {
+        if !c.is_ascii() || c.is_ascii_alphanumeric() || "#+-.:=@_".contains(c) {
+            ret.push(c);
+        } else {
+            ret.push_str(&format!("\\x{:02x}", c as u32));
+        }
+    }
+    ret
+}
+
+/// Decode a */dev/disk* symlink name encoded by udev
+fn decode_tag(name: &str) -> String {
+    let mut ret = vec!();
+    let bytes = name.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = name.get(i + 2..i + 4).filter(|_| bytes[i..].starts_with(b"\\x"))
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(c) => {
+                ret.push(c);
+                i += 4;
+            }
+            None => {
+                ret.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&ret).into_owned()
+}
+
+/// Resolve mount sources to block devices through the */dev/disk/by-** symlinks
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceResolver {
+    disk_dir: PathBuf,
+}
+
+impl Default for DeviceResolver {
+    fn default() -> DeviceResolver {
+        DeviceResolver::new()
+    }
+}
+
+impl DeviceResolver {
+    pub fn new() -> DeviceResolver {
+        DeviceResolver {
+            disk_dir: PathBuf::from(DISK_DIR),
+        }
+    }
+
+    /// Look up the tags in `path` instead of */dev/disk*
+    pub fn with_disk_dir<T>(mut self, path: T) -> DeviceResolver where T: Into<PathBuf> {
+        self.disk_dir = path.into();
+        self
+    }
+
+    /// Get the canonical device path of `source`, if it refers to an existing device
+    pub fn device_path(&self, source: &MountSource) -> io::Result<Option<PathBuf>> {
+        let path = match *source {
+            MountSource::Device(ref path) => path.clone(),
+            MountSource::Other(_) => return Ok(None),
+            _ => {
+                let (tag, value) = source.tag().unwrap();
+                let dir = TAGS.iter().find(|&&(t, _)| t == tag).unwrap().1;
+                self.disk_dir.join(dir).join(encode_tag(value))
+            }
+        };
+        match fs::canonicalize(path) {
+            Ok(path) => Ok(Some(path)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get the tags pointing to `device`, ordered as `UUID`, `LABEL`, `PARTUUID`, `PARTLABEL`
+    /// and `ID`
+    pub fn tags<T>(&self, device: T) -> io::Result<Vec<MountSource>> where T: AsRef<Path> {
+        let device = fs::canonicalize(device)?;
+        let mut ret = vec!();
+        for &(tag, dir) in TAGS {
+            let links = match fs::read_dir(self.disk_dir.join(dir)) {
+                Ok(l) => l,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            for link in links {
+                let link = link?;
+                if fs::canonicalize(link.path()).ok().as_ref() != Some(&device) {
+                    continue;
+                }
+                let value = decode_tag(&link.file_name().to_string_lossy());
+                let spec = format!("{}={}", tag, value);
+                ret.push(MountSource::parse(&spec));
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Get the device number of `source`, if it refers to an existing block device
+    pub fn device_id(&self, source: &MountSource) -> io::Result<Option<DeviceId>> {
+        let path = match self.device_path(source)? {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let metadata = fs::metadata(path)?;
+        if metadata.file_type().is_block_device() {
+            Ok(Some(DeviceId::from(metadata.rdev())))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use parse::MountEntry;
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::path::PathBuf;
+    use std::process;
+    use super::{DeviceResolver, MountSource};
+
+    #[test]
+    fn test_mount_source() {
+        let source = |line: &str| line.parse::<MountEntry>().unwrap().mount_source();
+        assert_eq!(source("/dev/mapper/foo-tmp /tmp ext4 rw 0 2"),
+                   MountSource::Device(PathBuf::from("/dev/mapper/foo-tmp")));
+        assert_eq!(source("UUID=1234-ABCD /boot vfat rw 0 2"), MountSource::Uuid("1234-ABCD".into()));
+        assert_eq!(MountSource::parse("LABEL=\"my disk\""), MountSource::Label("my disk".into()));
+        assert_eq!(source("PARTUUID=42 /mnt ext4 rw 0 2"), MountSource::PartUuid("42".into()));
+        assert_eq!(source("tmpfs /tmp tmpfs rw 0 0"), MountSource::Other("tmpfs".into()));
+        assert_eq!(source("server:/export /mnt nfs rw 0 0"), MountSource::Other("server:/export".into()));
+        assert_eq!(MountSource::Label("root".into()).to_string(), "LABEL=root");
+    }
+
+    #[test]
+    fn test_resolver() {
+        let dir = env::temp_dir().join(format!("mnt-disk-{}", process::id()));
+        fs::create_dir_all(dir.join("by-label")).unwrap();
+        symlink("/dev/null", dir.join("by-label").join("my\\x20disk")).unwrap();
+        symlink("/dev/zero", dir.join("by-label").join("données\\x2fété")).unwrap();
+        symlink("/dev/full", dir.join("by-label").join("My\\x28Disk\\x29")).unwrap();
+        let resolver = DeviceResolver::new().with_disk_dir(&dir);
+        let label = resolver.device_path(&MountSource::Label("my disk".into()));
+        let missing = resolver.device_path(&MountSource::Uuid("42".into()));
+        let not_block = resolver.device_id(&MountSource::Label("my disk".into()));
+        let non_ascii = resolver.device_path(&MountSource::Label("données/été".into()));
+        let punct = resolver.device_path(&MountSource::Label("My(Disk)".into()));
+        let tags = resolver.tags("/dev/null");
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(label.unwrap(), Some(PathBuf::from("/dev/null")));
+        assert_eq!(missing.unwrap(), None);
+        assert_eq!(not_block.unwrap(), None);
+        assert_eq!(non_ascii.unwrap(), Some(PathBuf::from("/dev/zero")));
+        assert_eq!(punct.unwrap(), Some(PathBuf::from("/dev/full")));
+        assert_eq!(tags.unwrap(), vec!(MountSource::Label("my disk".into())));
+        assert_eq!(resolver.device_path(&MountSource::Other("tmpfs".into())).unwrap(), None);
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use error::*;
+use fstabd::MergedFstab;
+use parse::{MountEntry, get_mount_from, get_submounts_from};
+use source::MountSource;
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Maximum number of symlinks to follow, as `MAXSYMLINKS` on Linux
+const MAX_SYMLINKS: usize = 40;
+
+/// Alternate root for the offline analysis of an image or a chroot
+///
+/// Paths are given as seen from inside the root, and symlinks are resolved without escaping it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sysroot {
+    root: PathBuf,
+}
+
+fn components(path: &Path) -> impl Iterator<Item = OsString> + '_ {
+    path.components().filter_map(|c| match c {
+        Component::Normal(name) => Some(name.to_os_string()),
+        Component::ParentDir => Some("..".into()),
+        _ => None,
+    })
+}
+
+impl Sysroot {
+    pub fn new<T>(root: T) -> Sysroot where T: Into<PathBuf> {
+        Sysroot {
+            root: root.into(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Get the host path of a path inside the root, without resolving it
+    pub fn host_path<T>(&self, path: T) -> PathBuf where T: AsRef<Path> {
+        let path = path.as_ref();
+        self.root.join(path.strip_prefix("/").unwrap_or(path))
+    }
+
+    /// Resolve the symlinks and `..` components of `path` inside the root
+    ///
+    /// Relative paths start from the root, absolute symlinks are relative to it and `..` never
+    /// goes above it.  Missing components are kept as is.  The returned path is seen from inside
+    /// the root, see `host_path()`.
+    pub fn resolve<T>(&self, path: T) -> io::Result<PathBuf> where T: AsRef<Path> {
+        let mut resolved = PathBuf::from("/");
+        let mut remaining: VecDeque<OsString> = components(path.as_ref()).collect();
+        let mut links = 0;
+        let mut missing = false;
+        while let Some(name) = remaining.pop_front() {
+            if name == ".." {
+                resolved.pop();
+                missing = false;
+                continue;
+            }
+            resolved.push(&name);
+            if missing {
+                continue;
+            }
+            let host = self.host_path(&resolved);
+            match fs::symlink_metadata(&host) {
+                Ok(ref m) if m.file_type().is_symlink() => {
+                    links += 1;
+                    if links > MAX_SYMLINKS {
+                        return Err(io::Error::new(io::ErrorKind::Other,
+                                                  format!("Too many levels of symbolic links: {}", host.display())));
+                    }
+                    let target = fs::read_link(&host)?;
+                    resolved.pop();
+                    if target.is_absolute() {
+                        resolved = PathBuf::from("/");
+                    }
+                    for (i, name) in components(&target).enumerate() {
+                        remaining.insert(i, name);
+                    }
+                }
+                Ok(_) => {}
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => missing = true,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Load the fstab of the root along with its */etc/fstab.d* fragments
+    ///
+    /// The files are resolved inside the root, like any other path.
+    pub fn fstab(&self) -> Result<MergedFstab, ParseError> {
+        MergedFstab::load_with(|path| self.resolve(path).map(|p| self.host_path(p)))
+    }
+
+    /// Resolve the target and the source path of `entry` inside the root
+    pub fn resolve_entry(&self, entry: &MountEntry) -> io::Result<MountEntry> {
+        let mut ret = entry.clone();
+        ret.file = self.resolve(&entry.file)?;
+        if let MountSource::Device(ref path) = entry.mount_source() {
+            ret.spec = self.resolve(path)?.into_os_string();
+        }
+        Ok(ret)
+    }
+
+    /// Get the fstab entries of the root, resolved inside it
+    pub fn entries(&self) -> Result<Vec<MountEntry>, ParseError> {
+        self.fstab()?.entries.into_iter().map(|e| {
+            self.resolve_entry(&e.entry).map_err(|err| ParseError::access(&e.entry.file, err).with_source_file(&e.origin.source_file))
+        }).collect()
+    }
+
+    /// Get the fstab entry the `target` path would be mounted from, as with `get_mount_from()`
+    pub fn get_mount<T>(&self, target: T) -> Result<Option<MountEntry>, ParseError> where T: AsRef<Path> {
+        let target = self.resolve(&target).map_err(|e| ParseError::access(target.as_ref(), e))?;
+        get_mount_from(target, self.entries()?.into_iter().map(Ok))
+    }
+
+    /// Get the fstab entries from `root` and beneath, as with `get_submounts_from()`
+    pub fn get_submounts<T>(&self, root: T) -> Result<Vec<MountEntry>, ParseError> where T: AsRef<Path> {
+        let root = self.resolve(&root).map_err(|e| ParseError::access(root.as_ref(), e))?;
+        get_submounts_from(root, self.entries()?.into_iter().map(Ok))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::path::Path;
+    use std::process;
+    use super::Sysroot;
+
+    #[test]
+    fn test_sysroot() {
+        let root = env::temp_dir().join(format!("mnt-sysroot-{}", process::id()));
+        fs::create_dir_all(root.join("etc")).unwrap();
+        fs::create_dir_all(root.join("srv/data")).unwrap();
+        fs::create_dir_all(root.join("run")).unwrap();
+        symlink("/run", root.join("var-run")).unwrap();
+        symlink("../../../..", root.join("srv/escape")).unwrap();
+        symlink("/etc", root.join("srv/data/etc")).unwrap();
+        fs::write(root.join("etc/fstab"), "\
+            /dev/sda1 / ext4 defaults 0 1\n\
+            /srv/data/../data /jail/data none bind 0 0\n\
+            tmpfs /var-run tmpfs defaults 0 0\n\
+        ").unwrap();
+        let sysroot = Sysroot::new(&root);
+        let escape = sysroot.resolve("/srv/escape/etc");
+        let inner = sysroot.resolve("srv/data/etc/fstab");
+        let missing = sysroot.resolve("/srv/data/missing/../foo");
+        let mount = sysroot.get_mount("/var-run/lock");
+        let submounts = sysroot.get_submounts("/jail");
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(escape.unwrap(), Path::new("/etc"));
+        assert_eq!(inner.unwrap(), Path::new("/etc/fstab"));
+        assert_eq!(missing.unwrap(), Path::new("/srv/data/foo"));
+        assert_eq!(sysroot.host_path("/etc/fstab"), root.join("etc/fstab"));
+        let mount = mount.unwrap().unwrap();
+        assert_eq!(mount.spec, "tmpfs");
+        assert_eq!(mount.file, Path::new("/run"));
+        let submounts = submounts.unwrap();
+        assert_eq!(submounts.len(), 1);
+        assert_eq!(submounts[0].spec, "/srv/data");
+    }
+
+    #[test]
+    fn test_fstab_symlink() {
+        let root = env::temp_dir().join(format!("mnt-sysroot-fstab-{}", process::id()));
+        let host = env::temp_dir().join(format!("mnt-sysroot-host-{}", process::id()));
+        fs::create_dir_all(root.join("etc/fstab.d")).unwrap();
+        fs::create_dir_all(root.join("srv")).unwrap();
+        fs::write(&host, "/dev/HOST /host ext4 defaults 0 2\n").unwrap();
+        symlink(&host, root.join("etc/fstab")).unwrap();
+        symlink(&host, root.join("etc/fstab.d/host.fstab")).unwrap();
+        symlink("/srv/inner", root.join("etc/fstab.d/inner.fstab")).unwrap();
+        fs::write(root.join("srv/inner"), "/dev/sda2 /srv ext4 defaults 0 2\n").unwrap();
+        let entries = Sysroot::new(&root).entries();
+        fs::remove_dir_all(&root).unwrap();
+        fs::remove_file(&host).unwrap();
+
+        let entries = entries.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].spec, "/dev/sda2");
+    }
+}
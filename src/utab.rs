@@ -0,0 +1,201 @@
+// Copyright (C) 2014-2015 Mickaël Salaün
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, version 3 of the License.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use error::*;
+use mountinfo::{MountInfoEntry, MountTable, unescape_octal};
+use parse::{MntOps, MountEntry, MountRefIter, fields};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::str;
+
+const RUN_UTAB: &str = "/run/mount/utab";
+
+/// Entry of the libmount *utab*, with the user-space options of a mount
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UtabEntry {
+    /// Mount ID, as in */proc/self/mountinfo*
+    pub mount_id: Option<u64>,
+    pub source: Option<OsString>,
+    pub target: Option<PathBuf>,
+    pub root: Option<PathBuf>,
+    /// Source of a bind mount
+    pub bind_source: Option<PathBuf>,
+    /// User-space options, e.g. `user=alice` or `x-gvfs-show`
+    pub mntops: Vec<MntOps>,
+    /// Mount helper attributes
+    pub attrs: Option<String>,
+}
+
+impl UtabEntry {
+    /// Parse a line of `KEY=value` fields, ignoring the unknown keys
+    pub fn parse<T>(line: &T) -> Result<UtabEntry, LineError> where T: AsRef<[u8]> + ?Sized {
+        let line = line.as_ref();
+        let mut ret = UtabEntry::default();
+        for span in fields(line) {
+            let field = &line[span.clone()];
+            let invalid = |name| LineError::new(LineErrorKind::InvalidField(name, String::from_utf8_lossy(field).into()), span.clone());
+            let (key, value) = match field.iter().position(|&c| c == b'=') {
+                Some(i) => (&field[..i], unescape_octal(&field[i + 1..])),
+                None => return Err(invalid("utab")),
+            };
+            let text = |name, value: Vec<u8>| String::from_utf8(value).map_err(|_| invalid(name));
+            match key {
+                b"ID" => ret.mount_id = Some(text("ID", value)?.parse().map_err(|_| invalid("ID"))?),
+                b"SRC" => ret.source = Some(OsString::from_vec(value)),
+                b"TARGET" => ret.target = Some(PathBuf::from(OsString::from_vec(value))),
+                b"ROOT" => ret.root = Some(PathBuf::from(OsString::from_vec(value))),
+                b"BINDSRC" => ret.bind_source = Some(PathBuf::from(OsString::from_vec(value))),
+                b"OPTS" => ret.mntops = text("OPTS", value)?.split_terminator(',').map(|o| o.parse().unwrap()).collect(),
+                b"ATTRS" => ret.attrs = Some(text("ATTRS", value)?),
+                _ => {}
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Get the user who mounted the filesystem, from the `user=` option
+    pub fn user(&self) -> Option<&str> {
+        self.mntops.iter().find_map(|o| match *o {
+            MntOps::Extra(ref extra) => extra.strip_prefix("user="),
+            _ => None,
+        })
+    }
+
+    /// Check if this entry describes the mount `entry`
+    ///
+    /// The target must match, as well as the mount ID, root and source when they are known,
+    /// because a stale entry may have the ID of a later mount.  Old utab files don't have IDs.
+    fn matches(&self, entry: &MountInfoEntry) -> bool {
+        self.target.as_ref() == Some(&entry.mount_point)
+            && self.mount_id.map_or(true, |id| id == entry.mount_id)
+            && self.root.as_ref().map_or(true, |r| *r == entry.root)
+            && self.source.as_ref().map_or(true, |s| *s == entry.source)
+    }
+}
+
+/// Content of the libmount *utab*
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Utab {
+    entries: Vec<UtabEntry>,
+}
+
+impl Utab {
+    pub fn new<T>(reader: T) -> Result<Utab, ParseError> where T: BufRead {
+        Utab::from_lines(MountRefIter::new(reader))
+    }
+
+    fn from_lines<T>(mut lines: MountRefIter<T>) -> Result<Utab, ParseError> where T: BufRead {
+        let mut entries = vec!();
+        while let Some(nb) = lines.next_line() {
+            let nb = nb.map_err(|e| lines.locate(From::from(e)))?;
+            let entry = UtabEntry::parse(lines.line())
+                .map_err(|e| lines.locate(ParseError::from_line(e, nb, lines.line())))?;
+            entries.push(entry);
+        }
+        Ok(Utab {
+            entries,
+        })
+    }
+
+    /// Load */run/mount/utab*, which doesn't exist if libmount never needed it
+    pub fn new_from_run() -> Result<Utab, ParseError> {
+        match File::open(RUN_UTAB) {
+            Ok(file) => Utab::from_lines(MountRefIter::new(BufReader::new(file)).with_source_file(RUN_UTAB)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Utab::default()),
+            Err(e) => Err(ParseError::from(e).with_source_file(RUN_UTAB)),
+        }
+    }
+
+    pub fn entries(&self) -> &[UtabEntry] {
+        &self.entries
+    }
+
+    /// Get the utab entry of a mount
+    pub fn get(&self, entry: &MountInfoEntry) -> Option<&UtabEntry> {
+        self.entries.iter().rev().find(|u| u.matches(entry))
+    }
+
+    pub fn get_by_target<T>(&self, target: T) -> Option<&UtabEntry> where T: AsRef<Path> {
+        self.entries.iter().rev().find(|u| u.target.as_deref() == Some(target.as_ref()))
+    }
+}
+
+impl MountInfoEntry {
+    /// Convert to a */proc/mounts* entry, along with the user-space options from `utab`
+    pub fn to_mount_entry_with(&self, utab: &Utab) -> MountEntry {
+        let mut ret = self.to_mount_entry();
+        for ops in utab.get(self).iter().flat_map(|u| u.mntops.iter()) {
+            if !ret.mntops.contains(ops) {
+                ret.mntops.push(ops.clone());
+            }
+        }
+        ret
+    }
+}
+
+impl MountTable {
+    /// Get the mount entries with the user-space options from `utab`, as libmount shows them
+    pub fn to_mount_entries_with(&self, utab: &Utab) -> Vec<MountEntry> {
+        self.entries().iter().map(|m| m.to_mount_entry_with(utab)).collect()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use mountinfo::MountTable;
+    use parse::MntOps;
+    use std::io::Cursor;
+    use std::path::Path;
+    use super::{Utab, UtabEntry};
+
+    const MOUNTINFO: &[u8] = b"\
+        22 1 254:0 / / rw,relatime - ext4 /dev/sda1 rw\n\
+        50 22 8:17 / /media/usb\\040key rw,nosuid,nodev,relatime - vfat /dev/sdb1 rw\n\
+        51 22 8:33 / /mnt/old rw,relatime - ext4 /dev/sdc1 rw\n\
+        52 22 8:49 / /mnt/new rw,relatime - ext4 /dev/sdd1 rw\n\
+    ";
+
+    const UTAB: &[u8] = b"\
+        ID=50 SRC=/dev/sdb1 TARGET=/media/usb\\040key ROOT=/ OPTS=uhelper=udisks2,user=alice\n\
+        SRC=/dev/sdc1 TARGET=/mnt/old ROOT=/ OPTS=x-mount.mkdir ATTRS=foo\n\
+        ID=52 SRC=/dev/sdb2 TARGET=/mnt/stale ROOT=/ OPTS=user=bob\n\
+    ";
+
+    #[test]
+    fn test_utab() {
+        let utab = Utab::new(Cursor::new(UTAB)).unwrap();
+        assert_eq!(utab.entries().len(), 3);
+        let usb = utab.get_by_target("/media/usb key").unwrap();
+        assert_eq!(usb.mount_id, Some(50));
+        assert_eq!(usb.user(), Some("alice"));
+        assert_eq!(utab.entries()[1].attrs, Some("foo".into()));
+        assert!(UtabEntry::parse("ID=foo").is_err());
+        assert_eq!(Utab::new(Cursor::new(b"ID=1\nfoo\n".as_ref())).unwrap_err().line_number(), Some(2));
+
+        let table = MountTable::from_fixture(MOUNTINFO);
+        let entries = table.to_mount_entries_with(&utab);
+        assert_eq!(entries[0].mntops, vec!(MntOps::Write(true), MntOps::RelAtime(true)));
+        assert_eq!(entries[1].file, Path::new("/media/usb key"));
+        assert_eq!(entries[1].mntops_set().get("user"), Some("alice"));
+        assert!(entries[2].mntops.contains(&MntOps::Extra("x-mount.mkdir".into())));
+        // Stale entry whose mount ID was reused
+        assert!(utab.get(&table.entries()[3]).is_none());
+        assert_eq!(entries[3].mntops_set().get("user"), None);
+        assert!(Utab::new_from_run().is_ok());
+    }
+}